@@ -15,97 +15,142 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use super::async_api::{
+    AsyncExecutor, AsyncPlanner, AsyncUdfClient, PassthroughQuery, SplitBatch, SplitPages,
+};
+use super::cancel::CancellationToken;
+use super::config::Configuration;
+use super::error::{Error, Result};
+use super::invoker::Invoker;
 use super::models;
 use super::requests;
-use bytes::{Buf, Bytes, IntoBuf};
-use rusoto_lambda::{InvocationRequest, InvocationResponse, Lambda, LambdaClient};
-use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use std::default::Default;
-
-#[derive(Default, Debug, Clone)]
-pub struct Configuration {
-    record_lambda: String,
-    metadata_lambda: String,
-    region: String,
+use super::spill::ObjectStore;
+use arrow::record_batch::RecordBatch;
+use futures::stream::StreamExt;
+use once_cell::sync::OnceCell;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// Returns the handle of the runtime driving the futures of the blocking API.
+/// The runtime is created on first use and shared by all clients.
+fn runtime() -> Result<Handle> {
+    static RUNTIME: OnceCell<Mutex<Runtime>> = OnceCell::new();
+    let runtime = RUNTIME.get_or_try_init(|| {
+        Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .map(Mutex::new)
+            .map_err(|e| Error::Transport(e.to_string()))
+    })?;
+    Ok(runtime.lock().unwrap().handle().clone())
 }
 
-impl Configuration {
-    pub fn new(lambda: String) -> Configuration {
-        Configuration {
-            record_lambda: lambda.clone(),
-            metadata_lambda: lambda.clone(),
-            region: "us-east-1".to_string(),
-        }
-    }
+/// Blocks the current thread on the future. The future is polled in the
+/// context of the shared runtime, whose threads drive its timers and I/O, so
+/// several threads can block on their futures at the same time.
+fn block_on<F: Future>(runtime: &Handle, future: F) -> F::Output {
+    runtime.enter(|| futures::executor::block_on(future))
 }
 
 /// The Planner class is responsible to resolve the metadata for each federation call.
 /// The first step is to check for tables and extract the table layout, once the table
 /// layout is fetched, we can extract the splits and based on the splits execute the
 /// ReadRecordRequests for each Split.
+///
+/// The Planner is a blocking wrapper around the `AsyncPlanner`.
 pub struct Planner {
-    config: Configuration,
-    client: LambdaClient,
+    inner: AsyncPlanner,
+    runtime: Handle,
 }
 
 impl Planner {
     /// Instantiates a new Planner object configured with a Configuration
-    /// object.
-    pub fn new(c: Configuration) -> Self {
-        let r = c.region.as_str().parse().unwrap();
-        Planner {
-            config: c,
-            client: LambdaClient::new(r),
-        }
+    /// object. The lambda functions are invoked using the AWS Lambda API.
+    pub fn new(c: Configuration) -> Result<Self> {
+        Self::from_async(AsyncPlanner::new(c)?)
     }
 
-    /// Generic invoke method to handle the request serialization and invocation.
-    /// The return value is automatically inferred and populated based on
-    /// the caller.
-    fn invoke<T>(&mut self, body: String) -> T
-    where
-        T: DeserializeOwned,
-    {
-        // Setup the request
-        let mut lambda_fun = InvocationRequest::default();
-        lambda_fun.function_name = self.config.metadata_lambda.clone();
+    /// Instantiates a new Planner object that uses the given `Invoker` to
+    /// call the metadata lambda function.
+    pub fn with_invoker(c: Configuration, invoker: Arc<dyn Invoker>) -> Result<Self> {
+        Self::from_async(AsyncPlanner::with_invoker(c, invoker))
+    }
 
-        // COnvert body to Bytes
-        lambda_fun.payload = Some(Bytes::from(body));
-        trace!("Invoking lambda function: {}", lambda_fun.function_name);
-        let result_future = self.client.invoke(lambda_fun);
-        let result = result_future.sync().unwrap();
+    /// Wraps the `AsyncPlanner` to drive its futures on the shared runtime.
+    pub fn from_async(inner: AsyncPlanner) -> Result<Self> {
+        Ok(Planner {
+            inner,
+            runtime: runtime()?,
+        })
+    }
 
-        // print the body
-        let payload = result.payload.unwrap();
-        trace!("{}", std::str::from_utf8(&payload).unwrap());
-        let reader = payload.into_buf().reader();
-        trace!("Result: {:?}", reader);
-        return serde_json::from_reader(reader).unwrap();
+    /// Returns the token that cancels the requests of the planner, e.g. from
+    /// another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.inner.cancellation_token()
     }
 
-    /// For a given catalog name, list all schemas inside the catalog
-    pub fn list_schemas(&mut self) -> requests::ListSchemasResponse {
-        let req = requests::ListSchemasRequest::default();
+    /// Returns the wire format used for the requests of the planner.
+    pub fn serde_version(&self) -> models::SerDeVersion {
+        self.inner.serde_version()
+    }
 
-        // Request should be converted to JSON
-        let body = serde_json::to_string(&req).unwrap();
-        let res: requests::ListSchemasResponse = self.invoke(body);
-        trace!("{:?}", res);
-        return res;
+    /// Pings the connector and negotiates the wire format used for all
+    /// subsequent requests of the planner.
+    pub fn ping(&mut self) -> Result<requests::PingResponse> {
+        block_on(&self.runtime, self.inner.ping())
+    }
+
+    /// Returns the optimizations supported by the connector for the catalog.
+    pub fn get_data_source_capabilities(
+        &mut self,
+        catalog_name: String,
+    ) -> Result<requests::GetDataSourceCapabilitiesResponse> {
+        block_on(
+            &self.runtime,
+            self.inner.get_data_source_capabilities(catalog_name),
+        )
+    }
+
+    /// Returns the optimizations supported by the connector for the catalog.
+    /// See `AsyncPlanner::get_capabilities`.
+    pub fn get_capabilities(&mut self, catalog_name: String) -> Result<models::Capabilities> {
+        block_on(&self.runtime, self.inner.get_capabilities(catalog_name))
+    }
+
+    /// Plans a native query of the connector using its query passthrough
+    /// function. See `AsyncPlanner::passthrough`.
+    pub fn passthrough(
+        &mut self,
+        catalog_name: String,
+        function_schema: String,
+        function_name: String,
+        arguments: HashMap<String, String>,
+    ) -> Result<PassthroughQuery> {
+        block_on(
+            &self.runtime,
+            self.inner
+                .passthrough(catalog_name, function_schema, function_name, arguments),
+        )
+    }
+
+    /// For a given catalog name, list all schemas inside the catalog
+    pub fn list_schemas(&mut self) -> Result<requests::ListSchemasResponse> {
+        block_on(&self.runtime, self.inner.list_schemas())
     }
 
     pub fn list_tables(
         &mut self,
         catalog_name: String,
         schema_name: String,
-    ) -> requests::ListTablesResponse {
-        let req = requests::ListTablesRequest::new(&"".to_owned(), &catalog_name, &schema_name);
-        let body = serde_json::to_string(&req).unwrap();
-        let res: requests::ListTablesResponse = self.invoke(body);
-        trace!("{:?}", res);
-        return res;
+    ) -> Result<requests::ListTablesResponse> {
+        block_on(
+            &self.runtime,
+            self.inner.list_tables(catalog_name, schema_name),
+        )
     }
 
     pub fn get_table(
@@ -113,12 +158,11 @@ impl Planner {
         catalog_name: String,
         schema_name: String,
         table_name: String,
-    ) -> requests::GetTableResponse {
-        let req = requests::GetTableRequest::new(catalog_name, schema_name, table_name);
-        let body = serde_json::to_string(&req).unwrap();
-        let res: requests::GetTableResponse = self.invoke(body);
-        trace!("{:?}", res);
-        return res;
+    ) -> Result<requests::GetTableResponse> {
+        block_on(
+            &self.runtime,
+            self.inner.get_table(catalog_name, schema_name, table_name),
+        )
     }
 
     pub fn get_table_layout(
@@ -128,20 +172,17 @@ impl Planner {
         constraints: models::Constraints,
         schema: models::Schema,
         partition_cols: Vec<String>,
-    ) -> requests::GetTableLayoutResponse {
-        let query_id = "".to_string();
-        let req = requests::GetTableLayoutRequest::new(
-            query_id,
-            catalog_name,
-            table_name,
-            constraints,
-            schema,
-            partition_cols,
-        );
-        let body = serde_json::to_string(&req).unwrap();
-        let res: requests::GetTableLayoutResponse = self.invoke(body);
-        trace!("{:?}", res);
-        return res;
+    ) -> Result<requests::GetTableLayoutResponse> {
+        block_on(
+            &self.runtime,
+            self.inner.get_table_layout(
+                catalog_name,
+                table_name,
+                constraints,
+                schema,
+                partition_cols,
+            ),
+        )
     }
 
     pub fn get_splits(
@@ -153,43 +194,218 @@ impl Planner {
         partition_cols: Vec<String>,
         constraints: models::Constraints,
         continuation_token: Option<String>,
-    ) -> requests::GetSplitsResponse {
-        let req = requests::GetSplitsRequest::new(
+    ) -> Result<requests::GetSplitsResponse> {
+        block_on(
+            &self.runtime,
+            self.inner.get_splits(
+                query_id,
+                catalog_name,
+                table_name,
+                partitions,
+                partition_cols,
+                constraints,
+                continuation_token,
+            ),
+        )
+    }
+
+    /// Returns an iterator over all splits of the table layout. The splits are
+    /// fetched lazily page by page, by re-issuing the `GetSplitsRequest` with the
+    /// continuation token of the previous response until the token is exhausted.
+    /// If `max_pages` is set, at most that many requests are issued. The iteration
+    /// stops after the first failed request.
+    pub fn all_splits(
+        &mut self,
+        query_id: String,
+        catalog_name: String,
+        table_name: models::TableName,
+        partitions: models::Block,
+        partition_cols: Vec<String>,
+        constraints: models::Constraints,
+        max_pages: Option<usize>,
+    ) -> SplitStream {
+        let pages = self.inner.split_pages(
             query_id,
             catalog_name,
             table_name,
             partitions,
             partition_cols,
             constraints,
-            continuation_token,
+            max_pages,
         );
-        let body = serde_json::to_string(&req).unwrap();
-        trace!("{:?}", body);
-        let res: requests::GetSplitsResponse = self.invoke(body);
-        trace!("{:?}", res);
-        return res;
+        SplitStream {
+            planner: self,
+            pages,
+            buffer: VecDeque::new(),
+        }
+    }
+}
+
+/// Iterator over the splits of all pages returned by the lambda function for
+/// a `GetSplitsRequest`.
+pub struct SplitStream<'a> {
+    planner: &'a mut Planner,
+    pages: SplitPages,
+    buffer: VecDeque<models::Split>,
+}
+
+impl<'a> SplitStream<'a> {
+    /// Returns the number of pages fetched so far.
+    pub fn pages(&self) -> usize {
+        self.pages.pages()
+    }
+}
+
+impl<'a> Iterator for SplitStream<'a> {
+    type Item = Result<models::Split>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(split) = self.buffer.pop_front() {
+                return Some(Ok(split));
+            }
+
+            let planner = &mut *self.planner;
+            match block_on(&planner.runtime, planner.inner.next_page(&mut self.pages))? {
+                Ok(splits) => self.buffer.extend(splits),
+                Err(e) => return Some(Err(e)),
+            }
+        }
     }
 }
 
 /// Wrapper class for the execution of a request against the lambda function.
+///
+/// The Executor is a blocking wrapper around the `AsyncExecutor`.
 pub struct Executor {
-    config: Configuration,
-    client: LambdaClient,
+    inner: AsyncExecutor,
+    runtime: Handle,
 }
 
 impl Executor {
-    /// Instantiates a new Planner object configured with a Configuration
-    /// object.
-    pub fn new(c: Configuration) -> Self {
-        let r = c.region.as_str().parse().unwrap();
-        Self {
-            config: c,
-            client: LambdaClient::new(r),
-        }
+    /// Instantiates a new Executor object configured with a Configuration
+    /// object. The lambda functions are invoked using the AWS Lambda API and
+    /// spilled blocks are read from S3.
+    pub fn new(c: Configuration) -> Result<Self> {
+        Self::from_async(AsyncExecutor::new(c)?)
+    }
+
+    /// Instantiates a new Executor object that uses the given `Invoker` to call
+    /// the record lambda function and reads spilled blocks from the given store.
+    pub fn with_invoker(
+        c: Configuration,
+        invoker: Arc<dyn Invoker>,
+        store: Arc<dyn ObjectStore>,
+    ) -> Result<Self> {
+        Self::from_async(AsyncExecutor::with_invoker(c, invoker, store))
+    }
+
+    /// Wraps the `AsyncExecutor` to drive its futures on the shared runtime.
+    pub fn from_async(inner: AsyncExecutor) -> Result<Self> {
+        Ok(Executor {
+            inner,
+            runtime: runtime()?,
+        })
+    }
+
+    /// Returns the token that cancels the reads of the executor, e.g. from
+    /// another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.inner.cancellation_token()
+    }
+
+    /// Returns the wire format used for the requests of the executor.
+    pub fn serde_version(&self) -> models::SerDeVersion {
+        self.inner.serde_version()
+    }
+
+    /// Uses the wire format of the given version for the requests, e.g. the
+    /// version negotiated by `Planner::ping`.
+    pub fn set_serde_version(&mut self, version: models::SerDeVersion) {
+        self.inner = self.inner.clone().with_serde_version(version);
+    }
+
+    /// Invokes the record lambda function for the split referenced in the request
+    /// and returns the response as sent by the connector. The block size limits
+    /// of the request are set from the configuration.
+    pub fn read_records_response(
+        &mut self,
+        req: requests::ReadRecordRequest,
+    ) -> Result<requests::RecordsResponse> {
+        block_on(&self.runtime, self.inner.read_records_response(req))
+    }
+
+    /// Reads the records of the split referenced in the request by invoking the
+    /// record lambda function. If the result fits into the response, the inline
+    /// block is returned, otherwise all blocks spilled to S3 are fetched and
+    /// returned in the order they were written.
+    pub fn read_records(&mut self, req: requests::ReadRecordRequest) -> Result<Vec<RecordBatch>> {
+        block_on(&self.runtime, self.inner.read_records(req))
+    }
+
+    /// Reads the records of all splits with at most `concurrency` splits read
+    /// at the same time, see `AsyncExecutor::read_all`. The batches are
+    /// returned while iterating in the order they were read.
+    pub fn read_all<'a>(
+        &'a mut self,
+        req: requests::ReadRecordRequest,
+        splits: Vec<models::Split>,
+        concurrency: usize,
+    ) -> impl Iterator<Item = Result<SplitBatch>> + 'a {
+        let runtime = self.runtime.clone();
+        let mut stream = self.inner.read_all(req, splits, concurrency);
+        std::iter::from_fn(move || block_on(&runtime, stream.next()))
     }
+}
 
-    pub fn read_records(&mut self, req: requests::ReadRecordRequest) {
-        trace!("Entering read_records()");
+/// The UdfClient evaluates the user defined functions of an Athena UDF lambda
+/// function on Arrow record batches.
+///
+/// The UdfClient is a blocking wrapper around the `AsyncUdfClient`.
+pub struct UdfClient {
+    inner: AsyncUdfClient,
+    runtime: Handle,
+}
+
+impl UdfClient {
+    /// Instantiates a new UdfClient object configured with a Configuration
+    /// object. The lambda function is invoked using the AWS Lambda API.
+    pub fn new(c: Configuration) -> Result<Self> {
+        Self::from_async(AsyncUdfClient::new(c)?)
+    }
+
+    /// Instantiates a new UdfClient object that uses the given `Invoker` to
+    /// call the lambda function.
+    pub fn with_invoker(c: Configuration, invoker: Arc<dyn Invoker>) -> Result<Self> {
+        Self::from_async(AsyncUdfClient::with_invoker(c, invoker))
+    }
+
+    /// Wraps the `AsyncUdfClient` to drive its futures on the shared runtime.
+    pub fn from_async(inner: AsyncUdfClient) -> Result<Self> {
+        Ok(UdfClient {
+            inner,
+            runtime: runtime()?,
+        })
+    }
+
+    /// Returns the token that cancels the invocations of the client, e.g. from
+    /// another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.inner.cancellation_token()
+    }
+
+    /// Evaluates the scalar function `method` of the UDF lambda function on the
+    /// rows of the input. See `AsyncUdfClient::invoke_scalar`.
+    pub fn invoke_scalar(
+        &mut self,
+        method: String,
+        input: RecordBatch,
+        output_schema: arrow::datatypes::Schema,
+    ) -> Result<RecordBatch> {
+        block_on(
+            &self.runtime,
+            self.inner.invoke_scalar(method, input, output_schema),
+        )
     }
 }
 
@@ -197,18 +413,416 @@ impl Executor {
 mod test {
 
     use super::*;
+    use crate::invoker::InvocationResult;
+    use crate::retry::{RetryEvent, RetryPolicy};
+    use crate::spill::InMemoryObjectStore;
+    use crate::test_util::{block_json, result, split_json, RECORDS, SCHEMA};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[test]
+    fn test_list_schemas_with_invoker() {
+        let invoker = |function_name: &str, payload: Vec<u8>| {
+            assert_eq!("metadata", function_name);
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            assert_eq!("ListSchemasRequest", req["@type"]);
+            result(serde_json::json!({
+                "@type": "ListSchemasResponse",
+                "requestType": "LIST_SCHEMAS",
+                "catalogName": "catalog",
+                "schemas": ["a", "b"]
+            }))
+        };
+
+        let c = Configuration::new("metadata".to_string());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        assert_eq!(vec!["a", "b"], p.list_schemas().unwrap().schemas);
+    }
+
+    #[test]
+    fn test_all_splits_pagination() {
+        let tokens = Arc::new(Mutex::new(Vec::new()));
+        let seen = tokens.clone();
+        let invoker = move |_: &str, payload: Vec<u8>| {
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            let token = req["continuationToken"].as_str().map(str::to_string);
+            seen.lock().unwrap().push(token.clone());
+            let (splits, next) = match token.as_ref().map(String::as_str) {
+                None => (vec![split_json("0"), split_json("1")], Some("1")),
+                Some("1") => (vec![split_json("2")], Some("2")),
+                _ => (vec![split_json("3")], None),
+            };
+            result(serde_json::json!({
+                "@type": "GetSplitsResponse",
+                "requestType": "GET_SPLITS",
+                "catalogName": "catalog",
+                "splits": splits,
+                "continuationToken": next
+            }))
+        };
+
+        let partitions: models::Block = serde_json::from_value(block_json()).unwrap();
+        let c = Configuration::new("metadata".to_string());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        let splits: Vec<models::Split> = p
+            .all_splits(
+                "query_id".to_string(),
+                "catalog".to_string(),
+                models::TableName::default(),
+                partitions.clone(),
+                vec![],
+                models::Constraints::default(),
+                None,
+            )
+            .collect::<Result<_>>()
+            .unwrap();
+        let keys: Vec<&str> = splits.iter().map(|s| s.spill_location().key()).collect();
+        assert_eq!(vec!["0", "1", "2", "3"], keys);
+        assert_eq!(
+            vec![None, Some("1".to_string()), Some("2".to_string())],
+            *tokens.lock().unwrap()
+        );
+
+        // The number of pages can be bounded
+        let mut stream = p.all_splits(
+            "query_id".to_string(),
+            "catalog".to_string(),
+            models::TableName::default(),
+            partitions,
+            vec![],
+            models::Constraints::default(),
+            Some(1),
+        );
+        assert_eq!(2, stream.by_ref().count());
+        assert_eq!(1, stream.pages());
+    }
+
+    #[test]
+    fn test_ping_negotiates_version() {
+        let invoker = |_: &str, payload: Vec<u8>| {
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            if req["@type"] == "PingRequest" {
+                return result(serde_json::json!({
+                    "@type": "PingResponse",
+                    "catalogName": "catalog",
+                    "queryId": "",
+                    "sourceType": "cloudwatch",
+                    "capabilities": 0,
+                    "serDeVersion": 2
+                }));
+            }
+
+            // The partitions are sent in the IPC format of the negotiated version
+            let records = base64::decode(req["partitions"]["records"].as_str().unwrap()).unwrap();
+            assert_eq!(base64::decode(RECORDS).unwrap(), records);
+            result(serde_json::json!({
+                "@type": "GetSplitsResponse",
+                "requestType": "GET_SPLITS",
+                "catalogName": "catalog",
+                "splits": [],
+                "continuationToken": null
+            }))
+        };
+
+        let partitions: models::Block = serde_json::from_value(block_json()).unwrap();
+        let c = Configuration::new("metadata".to_string());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        assert_eq!(models::SerDeVersion::V1, p.serde_version());
+        assert_eq!("cloudwatch", p.ping().unwrap().source_type);
+        assert_eq!(models::SerDeVersion::V2, p.serde_version());
+
+        let res = p
+            .get_splits(
+                "query_id".to_string(),
+                "catalog".to_string(),
+                models::TableName::default(),
+                partitions,
+                vec![],
+                models::Constraints::default(),
+                None,
+            )
+            .unwrap();
+        assert!(res.splits().is_empty());
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let invoker = |_: &str, payload: Vec<u8>| {
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            match req["@type"].as_str().unwrap() {
+                "PingRequest" => result(serde_json::json!({
+                    "@type": "PingResponse",
+                    "catalogName": "catalog",
+                    "queryId": "",
+                    "sourceType": "jdbc",
+                    "capabilities": 0,
+                    "serDeVersion": 5
+                })),
+                "GetDataSourceCapabilitiesRequest" => result(serde_json::json!({
+                    "@type": "GetDataSourceCapabilitiesResponse",
+                    "catalogName": "catalog",
+                    "capabilities": {
+                        "supports_query_passthrough": [
+                            { "subType": "system.query", "properties": ["QUERY"] }
+                        ]
+                    }
+                })),
+                "GetTableRequest" => {
+                    let args = &req["queryPassthroughArguments"];
+                    assert_eq!("system.query", args["schemaFunctionName"]);
+                    assert_eq!("SELECT 1", args["QUERY"]);
+                    result(serde_json::json!({
+                        "@type": "GetTableResponse",
+                        "requestType": "GET_TABLE",
+                        "catalogName": "catalog",
+                        "tableName": { "schemaName": "system", "tableName": "query" },
+                        "schema": { "schema": SCHEMA }
+                    }))
+                }
+                "GetTableLayoutRequest" => {
+                    let args = &req["constraints"]["queryPassthroughArguments"];
+                    assert_eq!("SELECT 1", args["QUERY"]);
+                    result(serde_json::json!({
+                        "@type": "GetTableLayoutResponse",
+                        "requestType": "GET_TABLE_LAYOUT",
+                        "catalogName": "catalog",
+                        "tableName": { "schemaName": "system", "tableName": "query" },
+                        "partitions": block_json()
+                    }))
+                }
+                "GetSplitsRequest" => result(serde_json::json!({
+                    "@type": "GetSplitsResponse",
+                    "requestType": "GET_SPLITS",
+                    "catalogName": "catalog",
+                    "splits": [split_json("0")],
+                    "continuationToken": null
+                })),
+                t => panic!("Unexpected request {}", t),
+            }
+        };
+
+        let c = Configuration::new("metadata".to_string());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        let mut args = HashMap::new();
+        args.insert("QUERY".to_string(), "SELECT 1".to_string());
+
+        // Query passthrough is only available once the version is negotiated
+        match p.passthrough(
+            "catalog".to_string(),
+            "system".to_string(),
+            "query".to_string(),
+            args.clone(),
+        ) {
+            Err(Error::Unsupported(_)) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        p.ping().unwrap();
+        let mut query = p
+            .passthrough(
+                "catalog".to_string(),
+                "system".to_string(),
+                "query".to_string(),
+                args.clone(),
+            )
+            .unwrap();
+        assert_eq!(1, query.splits.len());
+        assert!(query.constraints.is_query_passthrough());
+        assert_eq!(3, query.schema.get_schema().unwrap().fields().len());
+
+        match p.passthrough(
+            "catalog".to_string(),
+            "system".to_string(),
+            "unknown".to_string(),
+            args,
+        ) {
+            Err(Error::Unsupported(_)) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
 
     #[test]
-    fn default_test() {
-        let c = Configuration::default();
-        assert!(c.record_lambda.is_empty());
-        assert!(c.metadata_lambda.is_empty());
+    fn test_function_error() {
+        let invoker = |_: &str, _: Vec<u8>| {
+            Ok(InvocationResult {
+                payload: br#"{"errorMessage": "boom", "errorType": "java.lang.RuntimeException"}"#
+                    .to_vec(),
+                function_error: Some("Unhandled".to_string()),
+            })
+        };
+
+        let c = Configuration::new("metadata".to_string());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        match p.list_schemas() {
+            Err(Error::Connector(e)) => {
+                assert_eq!("java.lang.RuntimeException", e.error_type);
+                assert_eq!("boom", e.message);
+            }
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    /// Returns an invoker that fails the first `failures` invocations with a
+    /// throttling error of the connector. All invocations are counted.
+    fn throttled_invoker(failures: usize, invocations: Arc<Mutex<usize>>) -> impl Invoker {
+        move |_: &str, _: Vec<u8>| {
+            let mut invocations = invocations.lock().unwrap();
+            *invocations += 1;
+            if *invocations <= failures {
+                return Ok(InvocationResult {
+                    payload: br#"{"errorMessage": "Rate exceeded", "errorType": "com.amazonaws.services.logs.model.ThrottlingException"}"#
+                        .to_vec(),
+                    function_error: Some("Unhandled".to_string()),
+                });
+            }
+            result(serde_json::json!({
+                "@type": "ListSchemasResponse",
+                "requestType": "LIST_SCHEMAS",
+                "catalogName": "catalog",
+                "schemas": ["a"]
+            }))
+        }
     }
 
     #[test]
-    fn test_config_setup() {
-        let c = Configuration::new("this-is-my-arn".to_string());
-        assert_eq!("this-is-my-arn".to_string(), c.record_lambda);
-        assert_eq!(c.metadata_lambda, c.record_lambda);
+    fn test_retry_throttled() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        let policy = RetryPolicy::default()
+            .max_attempts(3)
+            .base_delay(Duration::from_millis(1))
+            .jitter(0.0)
+            .observer(move |function_name, event| {
+                assert_eq!("metadata", function_name);
+                observed.lock().unwrap().push(event);
+            });
+        let c = Configuration::builder()
+            .lambda("metadata".to_string())
+            .retry_policy(policy)
+            .build()
+            .unwrap();
+
+        let invocations = Arc::new(Mutex::new(0));
+        let invoker = throttled_invoker(2, invocations.clone());
+        let mut p = Planner::with_invoker(c.clone(), Arc::new(invoker)).unwrap();
+        assert_eq!(vec!["a"], p.list_schemas().unwrap().schemas);
+        assert_eq!(3, *invocations.lock().unwrap());
+        let delay = Duration::from_millis(1);
+        let mut expected = vec![
+            RetryEvent::Attempt(1),
+            RetryEvent::Retry(1, delay),
+            RetryEvent::Attempt(2),
+            RetryEvent::Retry(2, delay * 2),
+            RetryEvent::Attempt(3),
+        ];
+        assert_eq!(
+            expected,
+            events.lock().unwrap().drain(..).collect::<Vec<_>>()
+        );
+
+        let invocations = Arc::new(Mutex::new(0));
+        let invoker = throttled_invoker(3, invocations.clone());
+        let mut p = Planner::with_invoker(c, Arc::new(invoker)).unwrap();
+        match p.list_schemas() {
+            Err(Error::Connector(e)) => assert!(e.error_type.ends_with("ThrottlingException")),
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert_eq!(3, *invocations.lock().unwrap());
+        expected.push(RetryEvent::GiveUp(3));
+        assert_eq!(expected, *events.lock().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_region() {
+        let mut c = Configuration::new("lambda".to_string());
+        c.region = Some("moon-east-1".to_string());
+        assert!(Planner::new(c).is_err());
+    }
+
+    #[test]
+    fn test_read_records_inline() {
+        let invoker = |function_name: &str, payload: Vec<u8>| {
+            assert_eq!("records", function_name);
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            assert_eq!("ReadRecordsRequest", req["@type"]);
+            result(serde_json::json!({
+                "@type": "ReadRecordsResponse",
+                "requestType": "READ_RECORDS",
+                "catalogName": "catalog",
+                "records": block_json()
+            }))
+        };
+
+        let mut c = Configuration::new("metadata".to_string());
+        c.record_lambda = "records".to_string();
+        let mut e =
+            Executor::with_invoker(c, Arc::new(invoker), Arc::new(InMemoryObjectStore::new()))
+                .unwrap();
+        let batches = e
+            .read_records(requests::ReadRecordRequest::default())
+            .unwrap();
+        assert_eq!(1, batches.len());
+        assert_eq!(1, batches[0].num_rows());
+    }
+
+    #[test]
+    fn test_read_records_spilled() {
+        let invoker = |_: &str, _: Vec<u8>| {
+            result(serde_json::json!({
+                "@type": "RemoteReadRecordsResponse",
+                "requestType": "READ_RECORDS",
+                "catalogName": "catalog",
+                "schema": { "schema": SCHEMA },
+                "remoteBlocks": [
+                    { "@type": "S3SpillLocation", "bucket": "bucket", "key": "spill.0", "directory": false },
+                    { "@type": "S3SpillLocation", "bucket": "bucket", "key": "spill.1", "directory": false }
+                ],
+                "encryptionKey": null
+            }))
+        };
+
+        let store = Arc::new(InMemoryObjectStore::new());
+        store.put("bucket", "spill.0", base64::decode(RECORDS).unwrap());
+        store.put("bucket", "spill.1", base64::decode(RECORDS).unwrap());
+
+        let c = Configuration::new("lambda".to_string());
+        let mut e = Executor::with_invoker(c, Arc::new(invoker), store).unwrap();
+        let batches = e
+            .read_records(requests::ReadRecordRequest::default())
+            .unwrap();
+        assert_eq!(2, batches.len());
+    }
+
+    #[test]
+    fn test_read_all_spilled() {
+        let invoker = |_: &str, payload: Vec<u8>| {
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            result(serde_json::json!({
+                "@type": "RemoteReadRecordsResponse",
+                "requestType": "READ_RECORDS",
+                "catalogName": "catalog",
+                "schema": { "schema": SCHEMA },
+                "remoteBlocks": [req["split"]["spillLocation"]],
+                "encryptionKey": null
+            }))
+        };
+
+        let store = Arc::new(InMemoryObjectStore::new());
+        for key in &["a/split.0", "a/split.1", "b/split.0"] {
+            store.put("bucket", key, base64::decode(RECORDS).unwrap());
+        }
+        let splits: Vec<models::Split> = ["a", "b"]
+            .iter()
+            .map(|key| serde_json::from_value(split_json(key)).unwrap())
+            .collect();
+
+        let c = Configuration::new("lambda".to_string());
+        let mut e = Executor::with_invoker(c, Arc::new(invoker), store).unwrap();
+        let mut batches: Vec<(usize, usize)> = e
+            .read_all(requests::ReadRecordRequest::default(), splits, 2)
+            .map(|b| b.map(|b| (b.split_index, b.batch_index)))
+            .collect::<Result<_>>()
+            .unwrap();
+        batches.sort();
+        assert_eq!(vec![(0, 0), (0, 1), (1, 0)], batches);
     }
 }
@@ -0,0 +1,128 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::error::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// The parsed ARN of a lambda function, e.g.
+/// `arn:aws:lambda:eu-central-1:123456789012:function:cwtest:prod`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LambdaArn {
+    pub partition: String,
+    pub region: String,
+    pub account: String,
+    pub function_name: String,
+    /// The version or alias of the function, if present.
+    pub qualifier: Option<String>,
+}
+
+impl LambdaArn {
+    /// Returns true if the function name looks like an ARN rather than a plain
+    /// function name.
+    pub fn is_arn(name: &str) -> bool {
+        name.starts_with("arn:")
+    }
+}
+
+impl FromStr for LambdaArn {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Config(format!("Invalid lambda function ARN `{}`", s));
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() < 7 || parts.len() > 8 {
+            return Err(invalid());
+        }
+        if parts[0] != "arn" || parts[2] != "lambda" || parts[5] != "function" {
+            return Err(invalid());
+        }
+        if parts[1].is_empty() || parts[3].is_empty() || parts[6].is_empty() {
+            return Err(invalid());
+        }
+
+        let qualifier = match parts.get(7) {
+            Some(q) if q.is_empty() => return Err(invalid()),
+            Some(q) => Some(q.to_string()),
+            None => None,
+        };
+        Ok(LambdaArn {
+            partition: parts[1].to_string(),
+            region: parts[3].to_string(),
+            account: parts[4].to_string(),
+            function_name: parts[6].to_string(),
+            qualifier,
+        })
+    }
+}
+
+impl fmt::Display for LambdaArn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "arn:{}:lambda:{}:{}:function:{}",
+            self.partition, self.region, self.account, self.function_name
+        )?;
+        if let Some(qualifier) = &self.qualifier {
+            write!(f, ":{}", qualifier)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_arn() {
+        let arn: LambdaArn = "arn:aws:lambda:eu-central-1:123:function:cwtest"
+            .parse()
+            .unwrap();
+        assert_eq!("aws", arn.partition);
+        assert_eq!("eu-central-1", arn.region);
+        assert_eq!("123", arn.account);
+        assert_eq!("cwtest", arn.function_name);
+        assert!(arn.qualifier.is_none());
+        assert_eq!(
+            "arn:aws:lambda:eu-central-1:123:function:cwtest",
+            arn.to_string()
+        );
+
+        let arn: LambdaArn = "arn:aws-cn:lambda:cn-north-1:123:function:cwtest:prod"
+            .parse()
+            .unwrap();
+        assert_eq!("aws-cn", arn.partition);
+        assert_eq!(Some("prod".to_string()), arn.qualifier);
+    }
+
+    #[test]
+    fn test_parse_invalid_arn() {
+        assert!("cwtest".parse::<LambdaArn>().is_err());
+        assert!("arn:aws:s3:::bucket".parse::<LambdaArn>().is_err());
+        assert!("arn:aws:lambda:us-east-1:123:layer:cwtest"
+            .parse::<LambdaArn>()
+            .is_err());
+        assert!("arn:aws:lambda::123:function:cwtest"
+            .parse::<LambdaArn>()
+            .is_err());
+        assert!("arn:aws:lambda:us-east-1:123:function:cwtest:"
+            .parse::<LambdaArn>()
+            .is_err());
+    }
+}
@@ -0,0 +1,1197 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::cancel::CancellationToken;
+use super::config::Configuration;
+use super::error::{ConnectorError, Error, Result};
+use super::invoker::{Invoker, LambdaInvoker};
+use super::models::{self, SerDeVersion};
+use super::requests;
+use super::retry::RetryEvent;
+use super::spill::{ObjectStore, S3ObjectStore, SpillReader};
+use arrow::record_batch::RecordBatch;
+use futures::future::{self, Future};
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::time::{self, Instant};
+
+/// Generic invoke method to handle the request serialization and the invocation
+/// of the lambda function with the given name. The return value is automatically
+/// inferred and populated based on the caller. The request is serialized in the
+/// wire format of the given version. Failed invocations are retried according
+/// to the retry policy of the configuration. The token is checked before every
+/// attempt, so a query cancelled during the backoff is not invoked again.
+async fn invoke<R, T>(
+    invoker: &dyn Invoker,
+    config: &Configuration,
+    cancellation: &CancellationToken,
+    version: SerDeVersion,
+    function_name: &str,
+    req: &R,
+) -> Result<T>
+where
+    R: requests::Request,
+    T: DeserializeOwned,
+{
+    let body = requests::to_payload(req, version)?;
+    let policy = config.retry_policy();
+    let mut attempt = 1;
+    loop {
+        cancellation.check()?;
+        let deadline = config.call_timeout().map(|t| Instant::now() + t);
+        let res = with_deadline(
+            deadline,
+            invoke_once(invoker, function_name, body.clone()),
+            || format!("The invocation of {} timed out", function_name),
+        );
+        policy.observe(function_name, RetryEvent::Attempt(attempt));
+        match res.await {
+            Err(e) if policy.should_retry(attempt, &e) => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "Attempt {} to invoke {} failed, retrying in {:?}: {}",
+                    attempt, function_name, delay, e
+                );
+                policy.observe(function_name, RetryEvent::Retry(attempt, delay));
+                tokio::time::delay_for(delay).await;
+                attempt += 1;
+            }
+            Err(e) if policy.is_retryable(&e) => {
+                policy.observe(function_name, RetryEvent::GiveUp(attempt));
+                return Err(e);
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Invokes the lambda function once and decodes the response.
+async fn invoke_once<T>(invoker: &dyn Invoker, function_name: &str, body: Vec<u8>) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    trace!("Invoking lambda function: {}", function_name);
+    let result = invoker.invoke(function_name, body).await?;
+
+    if let Some(function_error) = result.function_error {
+        let e = ConnectorError::from_payload(&function_error, &result.payload);
+        error!("Lambda function {} failed: {}", function_name, e);
+        return Err(Error::Connector(e));
+    }
+
+    // print the body
+    let payload = std::str::from_utf8(&result.payload)?;
+    trace!("{}", payload);
+    Ok(serde_json::from_str(payload)?)
+}
+
+/// Bounds the future by the deadline, if any. The message of the timeout error
+/// is created lazily.
+async fn with_deadline<T, F, M>(deadline: Option<Instant>, f: F, msg: M) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+    M: FnOnce() -> String,
+{
+    match deadline {
+        Some(deadline) => time::timeout_at(deadline, f)
+            .await
+            .map_err(|_| Error::Timeout(msg()))?,
+        None => f.await,
+    }
+}
+
+/// Returns the deadline of a query started now.
+fn query_deadline(config: &Configuration) -> Option<Instant> {
+    config.query_timeout().map(|t| Instant::now() + t)
+}
+
+/// Message of the timeout of reading the splits.
+const READ_TIMEOUT: &str = "Reading the splits exceeded the query timeout";
+
+/// Checks the token before every item of the stream is fetched and fails the
+/// stream once the deadline expires. The stream ends after the first error.
+fn guarded<'a, T: Send + 'a>(
+    stream: BoxStream<'a, Result<T>>,
+    cancellation: CancellationToken,
+    deadline: Option<Instant>,
+) -> BoxStream<'a, Result<T>> {
+    stream::unfold(Some(stream), move |state| {
+        let cancellation = cancellation.clone();
+        async move {
+            let mut stream = state?;
+            let next = with_deadline(
+                deadline,
+                async {
+                    cancellation.check()?;
+                    Ok(stream.next().await)
+                },
+                || READ_TIMEOUT.to_string(),
+            )
+            .await;
+            match next {
+                Ok(Some(Ok(item))) => Some((Ok(item), Some(stream))),
+                Ok(Some(Err(e))) | Err(e) => Some((Err(e), None)),
+                Ok(None) => None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Pagination state of the `GetSplitsRequest`s issued for a single table
+/// layout. The state is shared by the blocking and the async iteration over
+/// all splits.
+pub(crate) struct SplitPages {
+    query_id: String,
+    catalog_name: String,
+    table_name: models::TableName,
+    partitions: models::Block,
+    partition_cols: Vec<String>,
+    constraints: models::Constraints,
+    max_pages: Option<usize>,
+    deadline: Option<Instant>,
+    pages: usize,
+    continuation_token: Option<String>,
+    exhausted: bool,
+}
+
+impl SplitPages {
+    pub(crate) fn new(
+        query_id: String,
+        catalog_name: String,
+        table_name: models::TableName,
+        partitions: models::Block,
+        partition_cols: Vec<String>,
+        constraints: models::Constraints,
+        max_pages: Option<usize>,
+        deadline: Option<Instant>,
+    ) -> Self {
+        SplitPages {
+            query_id,
+            catalog_name,
+            table_name,
+            partitions,
+            partition_cols,
+            constraints,
+            max_pages,
+            deadline,
+            pages: 0,
+            continuation_token: None,
+            exhausted: false,
+        }
+    }
+
+    /// Returns the number of pages fetched so far.
+    pub(crate) fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Returns the request for the next page or `None` if all pages were
+    /// fetched or the maximum number of pages is reached.
+    pub(crate) fn next_request(&mut self) -> Option<requests::GetSplitsRequest> {
+        if self.exhausted || self.max_pages.map_or(false, |max| self.pages >= max) {
+            return None;
+        }
+
+        self.pages += 1;
+        Some(requests::GetSplitsRequest::new(
+            self.query_id.clone(),
+            self.catalog_name.clone(),
+            self.table_name.clone(),
+            self.partitions.clone(),
+            self.partition_cols.clone(),
+            self.constraints.clone(),
+            self.continuation_token.take(),
+        ))
+    }
+
+    /// Records the result of the last request and returns the splits of the
+    /// page. After the first failed request, no more pages are fetched.
+    pub(crate) fn on_response(
+        &mut self,
+        res: Result<requests::GetSplitsResponse>,
+    ) -> Result<Vec<models::Split>> {
+        match res {
+            Ok(res) => {
+                self.continuation_token = res.continuation_token().map(str::to_string);
+                self.exhausted = self.continuation_token.is_none();
+                Ok(res.into_splits())
+            }
+            Err(e) => {
+                self.exhausted = true;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Async counterpart of the `Planner`. All methods return futures that can be
+/// driven by a tokio runtime. The planner can be shared between tasks.
+#[derive(Clone)]
+pub struct AsyncPlanner {
+    config: Configuration,
+    invoker: Arc<dyn Invoker>,
+    cancellation: CancellationToken,
+    serde_version: Arc<RwLock<SerDeVersion>>,
+}
+
+impl AsyncPlanner {
+    /// Instantiates a new AsyncPlanner object configured with a Configuration
+    /// object. The lambda functions are invoked using the AWS Lambda API.
+    pub fn new(c: Configuration) -> Result<Self> {
+        let r = c.region()?;
+        Ok(Self::with_invoker(c, Arc::new(LambdaInvoker::new(r))))
+    }
+
+    /// Instantiates a new AsyncPlanner object that uses the given `Invoker` to
+    /// call the metadata lambda function.
+    pub fn with_invoker(c: Configuration, invoker: Arc<dyn Invoker>) -> Self {
+        AsyncPlanner {
+            config: c,
+            invoker,
+            cancellation: CancellationToken::new(),
+            serde_version: Arc::new(RwLock::new(SerDeVersion::V1)),
+        }
+    }
+
+    /// Uses the token to cancel the requests of the planner. To cancel single
+    /// queries, use a clone of the planner with a new token per query.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Returns the token that cancels the requests of the planner.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Returns the invoker used by the planner, e.g. to share the client with an
+    /// `AsyncExecutor`.
+    pub fn invoker(&self) -> Arc<dyn Invoker> {
+        self.invoker.clone()
+    }
+
+    /// Returns the wire format used for the requests of the planner. Until the
+    /// version is negotiated with `ping`, the first version is used, which
+    /// every connector understands.
+    pub fn serde_version(&self) -> SerDeVersion {
+        *self.serde_version.read().unwrap()
+    }
+
+    /// Invokes the metadata lambda function with the serialized request.
+    async fn invoke<R, T>(&self, req: &R) -> Result<T>
+    where
+        R: requests::Request,
+        T: DeserializeOwned,
+    {
+        if self.config.metadata_lambda.is_empty() {
+            return Err(Error::Config("Missing metadata lambda".to_string()));
+        }
+        invoke(
+            self.invoker.as_ref(),
+            &self.config,
+            &self.cancellation,
+            self.serde_version(),
+            &self.config.metadata_function(),
+            req,
+        )
+        .await
+    }
+
+    /// Pings the connector and negotiates the wire format used for all
+    /// subsequent requests of the planner and its clones.
+    pub async fn ping(&self) -> Result<requests::PingResponse> {
+        let req = requests::PingRequest::new(String::new(), String::new());
+        let res: requests::PingResponse = self.invoke(&req).await?;
+        let version = SerDeVersion::negotiate(res.ser_de_version);
+        info!(
+            "Connector {} supports SerDe version {}, using version {}",
+            res.source_type,
+            res.ser_de_version.value(),
+            version.value()
+        );
+        *self.serde_version.write().unwrap() = version;
+        Ok(res)
+    }
+
+    /// Returns the optimizations supported by the connector for the catalog.
+    pub async fn get_data_source_capabilities(
+        &self,
+        catalog_name: String,
+    ) -> Result<requests::GetDataSourceCapabilitiesResponse> {
+        let req = requests::GetDataSourceCapabilitiesRequest::new(String::new(), catalog_name);
+        self.invoke(&req).await
+    }
+
+    /// Returns the optimizations supported by the connector for the catalog.
+    /// Connectors before SerDe version 4 do not report their capabilities and
+    /// support none of the optimizations.
+    pub async fn get_capabilities(&self, catalog_name: String) -> Result<models::Capabilities> {
+        if self.serde_version() < SerDeVersion::V4 {
+            return Ok(models::Capabilities::default());
+        }
+        Ok(self
+            .get_data_source_capabilities(catalog_name)
+            .await?
+            .into())
+    }
+
+    /// For a given catalog name, list all schemas inside the catalog
+    pub async fn list_schemas(&self) -> Result<requests::ListSchemasResponse> {
+        let req = requests::ListSchemasRequest::default();
+        self.invoke(&req).await
+    }
+
+    pub async fn list_tables(
+        &self,
+        catalog_name: String,
+        schema_name: String,
+    ) -> Result<requests::ListTablesResponse> {
+        let req = requests::ListTablesRequest::new(&"".to_owned(), &catalog_name, &schema_name);
+        self.invoke(&req).await
+    }
+
+    pub async fn get_table(
+        &self,
+        catalog_name: String,
+        schema_name: String,
+        table_name: String,
+    ) -> Result<requests::GetTableResponse> {
+        let req = requests::GetTableRequest::new(catalog_name, schema_name, table_name);
+        self.invoke(&req).await
+    }
+
+    pub async fn get_table_layout(
+        &self,
+        catalog_name: String,
+        table_name: models::TableName,
+        constraints: models::Constraints,
+        schema: models::Schema,
+        partition_cols: Vec<String>,
+    ) -> Result<requests::GetTableLayoutResponse> {
+        let query_id = "".to_string();
+        let req = requests::GetTableLayoutRequest::new(
+            query_id,
+            catalog_name,
+            table_name,
+            constraints,
+            schema,
+            partition_cols,
+        );
+        self.invoke(&req).await
+    }
+
+    pub async fn get_splits(
+        &self,
+        query_id: String,
+        catalog_name: String,
+        table_name: models::TableName,
+        partitions: models::Block,
+        partition_cols: Vec<String>,
+        constraints: models::Constraints,
+        continuation_token: Option<String>,
+    ) -> Result<requests::GetSplitsResponse> {
+        let req = requests::GetSplitsRequest::new(
+            query_id,
+            catalog_name,
+            table_name,
+            partitions,
+            partition_cols,
+            constraints,
+            continuation_token,
+        );
+        self.invoke(&req).await
+    }
+
+    /// Plans a native query of the connector using its query passthrough
+    /// function `function_schema.function_name`, e.g. `system.query`. The
+    /// connector must list the function in its capabilities. The arguments
+    /// of the function, e.g. the query, are validated by the connector.
+    ///
+    /// Returns the schema of the query and all its splits. The records of the
+    /// splits are read with `ReadRecordRequest`s using the returned
+    /// constraints.
+    pub async fn passthrough(
+        &self,
+        catalog_name: String,
+        function_schema: String,
+        function_name: String,
+        arguments: HashMap<String, String>,
+    ) -> Result<PassthroughQuery> {
+        let version = self.serde_version();
+        if version < SerDeVersion::V5 {
+            return Err(Error::Unsupported(format!(
+                "Query passthrough requires SerDe version 5, the connector uses version {}",
+                version.value()
+            )));
+        }
+
+        let capabilities = self.get_capabilities(catalog_name.clone()).await?;
+        if !capabilities.supports_passthrough(&function_schema, &function_name) {
+            return Err(Error::Unsupported(format!(
+                "Query passthrough function {}.{} is not supported by catalog {}",
+                function_schema, function_name, catalog_name
+            )));
+        }
+
+        let mut arguments = arguments;
+        arguments.insert(
+            models::Constraints::SCHEMA_FUNCTION_NAME.to_string(),
+            format!("{}.{}", function_schema, function_name),
+        );
+        let req =
+            requests::GetTableRequest::new(catalog_name.clone(), function_schema, function_name)
+                .with_query_passthrough_arguments(arguments.clone());
+        let table: requests::GetTableResponse = self.invoke(&req).await?;
+
+        let constraints =
+            models::Constraints::default().with_query_passthrough_arguments(arguments);
+        let layout = self
+            .get_table_layout(
+                catalog_name.clone(),
+                table.table_name.clone(),
+                constraints.clone(),
+                table.schema.clone(),
+                vec![],
+            )
+            .await?;
+        let splits = self
+            .all_splits(
+                String::new(),
+                catalog_name,
+                table.table_name.clone(),
+                layout.partitions,
+                vec![],
+                constraints.clone(),
+                None,
+            )
+            .try_collect()
+            .await?;
+
+        Ok(PassthroughQuery {
+            table_name: table.table_name,
+            schema: table.schema,
+            constraints,
+            splits,
+        })
+    }
+
+    /// Creates the pagination state for fetching all splits of the table layout.
+    /// The deadline of the query starts now.
+    pub(crate) fn split_pages(
+        &self,
+        query_id: String,
+        catalog_name: String,
+        table_name: models::TableName,
+        partitions: models::Block,
+        partition_cols: Vec<String>,
+        constraints: models::Constraints,
+        max_pages: Option<usize>,
+    ) -> SplitPages {
+        SplitPages::new(
+            query_id,
+            catalog_name,
+            table_name,
+            partitions,
+            partition_cols,
+            constraints,
+            max_pages,
+            query_deadline(&self.config),
+        )
+    }
+
+    /// Fetches the next page of splits or returns `None` if all pages were
+    /// fetched. The request is bounded by the deadline of the query.
+    pub(crate) async fn next_page(
+        &self,
+        pages: &mut SplitPages,
+    ) -> Option<Result<Vec<models::Split>>> {
+        let req = pages.next_request()?;
+        let res = with_deadline(pages.deadline, self.invoke(&req), || {
+            "Fetching the splits exceeded the query timeout".to_string()
+        })
+        .await;
+        Some(pages.on_response(res))
+    }
+
+    /// Returns a stream over all splits of the table layout. The splits are
+    /// fetched lazily page by page, by re-issuing the `GetSplitsRequest` with the
+    /// continuation token of the previous response until the token is exhausted.
+    /// If `max_pages` is set, at most that many requests are issued. The stream
+    /// ends after the first failed request, including the cancellation of the
+    /// planner or the expiry of the query timeout.
+    pub fn all_splits<'a>(
+        &'a self,
+        query_id: String,
+        catalog_name: String,
+        table_name: models::TableName,
+        partitions: models::Block,
+        partition_cols: Vec<String>,
+        constraints: models::Constraints,
+        max_pages: Option<usize>,
+    ) -> impl Stream<Item = Result<models::Split>> + 'a {
+        let pages = self.split_pages(
+            query_id,
+            catalog_name,
+            table_name,
+            partitions,
+            partition_cols,
+            constraints,
+            max_pages,
+        );
+        stream::unfold(pages, move |mut pages| async move {
+            let splits = self.next_page(&mut pages).await?;
+            Some((splits, pages))
+        })
+        .map_ok(|splits| stream::iter(splits.into_iter().map(Ok)))
+        .try_flatten()
+    }
+}
+
+/// A native query planned with `passthrough`.
+#[derive(Debug)]
+pub struct PassthroughQuery {
+    /// The table name reported by the connector for the query.
+    pub table_name: models::TableName,
+    /// The schema of the result of the query.
+    pub schema: models::Schema,
+    /// The constraints carrying the arguments of the passthrough function,
+    /// to be passed to the `ReadRecordRequest`s.
+    pub constraints: models::Constraints,
+    pub splits: Vec<models::Split>,
+}
+
+/// A `RecordBatch` read by `read_all` together with its position in the
+/// result of the query.
+#[derive(Debug)]
+pub struct SplitBatch {
+    /// Index of the split in the list of splits passed to `read_all`.
+    pub split_index: usize,
+    /// Index of the batch within the batches of the split.
+    pub batch_index: usize,
+    pub batch: RecordBatch,
+}
+
+/// Async counterpart of the `Executor`. All methods return futures that can be
+/// driven by a tokio runtime. The executor can be shared between tasks.
+#[derive(Clone)]
+pub struct AsyncExecutor {
+    config: Configuration,
+    invoker: Arc<dyn Invoker>,
+    spill_reader: SpillReader,
+    cancellation: CancellationToken,
+    serde_version: SerDeVersion,
+}
+
+impl AsyncExecutor {
+    /// Instantiates a new AsyncExecutor object configured with a Configuration
+    /// object. The lambda functions are invoked using the AWS Lambda API and
+    /// spilled blocks are read from S3.
+    pub fn new(c: Configuration) -> Result<Self> {
+        let r = c.region()?;
+        Ok(Self::with_invoker(
+            c,
+            Arc::new(LambdaInvoker::new(r.clone())),
+            Arc::new(S3ObjectStore::new(r)),
+        ))
+    }
+
+    /// Instantiates a new AsyncExecutor object that uses the given `Invoker` to
+    /// call the record lambda function and reads spilled blocks from the given
+    /// store.
+    pub fn with_invoker(
+        c: Configuration,
+        invoker: Arc<dyn Invoker>,
+        store: Arc<dyn ObjectStore>,
+    ) -> Self {
+        AsyncExecutor {
+            config: c,
+            invoker,
+            spill_reader: SpillReader::new(store),
+            cancellation: CancellationToken::new(),
+            serde_version: SerDeVersion::V1,
+        }
+    }
+
+    /// Uses the token to cancel the reads of the executor. To cancel single
+    /// queries, use a clone of the executor with a new token per query.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Returns the token that cancels the reads of the executor.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Uses the wire format of the given version for the requests, e.g. the
+    /// version negotiated by `AsyncPlanner::ping`. Until a version is set,
+    /// the first version is used.
+    pub fn with_serde_version(mut self, version: SerDeVersion) -> Self {
+        self.serde_version = version;
+        self
+    }
+
+    pub fn serde_version(&self) -> SerDeVersion {
+        self.serde_version
+    }
+
+    /// Invokes the record lambda function for the split referenced in the request
+    /// and returns the response as sent by the connector. The block size limits
+    /// of the request are set from the configuration.
+    pub async fn read_records_response(
+        &self,
+        mut req: requests::ReadRecordRequest,
+    ) -> Result<requests::RecordsResponse> {
+        trace!("Entering read_records_response()");
+        if self.config.record_lambda.is_empty() {
+            return Err(Error::Config("Missing record lambda".to_string()));
+        }
+        req.set_block_sizes(
+            self.config.max_block_size(),
+            self.config.max_inline_block_size(),
+        );
+        let res = invoke(
+            self.invoker.as_ref(),
+            &self.config,
+            &self.cancellation,
+            self.serde_version,
+            &self.config.record_function(),
+            &req,
+        )
+        .await?;
+        trace!("{:?}", res);
+        Ok(res)
+    }
+
+    /// Reads the records of the split referenced in the request by invoking the
+    /// record lambda function. If the result fits into the response, the inline
+    /// block is returned, otherwise all blocks spilled to S3 are fetched and
+    /// returned in the order they were written. The executor stops fetching
+    /// spilled blocks once it is cancelled.
+    pub async fn read_records(&self, req: requests::ReadRecordRequest) -> Result<Vec<RecordBatch>> {
+        trace!("Entering read_records()");
+        self.read_split(req, None).try_collect().await
+    }
+
+    /// Returns a stream over the batches of the split referenced in the request.
+    /// Spilled blocks are fetched one at a time while polling the stream, so the
+    /// batches of a split are never buffered.
+    fn read_split(
+        &self,
+        req: requests::ReadRecordRequest,
+        deadline: Option<Instant>,
+    ) -> BoxStream<'_, Result<RecordBatch>> {
+        let response = with_deadline(deadline, self.read_records_response(req), || {
+            READ_TIMEOUT.to_string()
+        });
+        let spill_reader = self.spill_reader.clone();
+        let cancellation = self.cancellation.clone();
+        stream::once(response)
+            .map_ok(move |res| match res {
+                requests::RecordsResponse::ReadRecordsResponse(res) => {
+                    stream::once(future::ok(res.records.into_record_batch())).boxed()
+                }
+                requests::RecordsResponse::RemoteReadRecordsResponse(mut res) => {
+                    let schema = match res.schema.get_schema() {
+                        Ok(schema) => Arc::new(schema),
+                        Err(e) => return stream::once(future::err(e)).boxed(),
+                    };
+                    let spill_reader = spill_reader.clone();
+                    let key = res.encryption_key;
+                    let blocks = stream::iter(res.remote_blocks)
+                        .map(move |location| {
+                            spill_reader.read(&location, schema.clone(), key.as_ref())
+                        })
+                        .flatten()
+                        .boxed();
+                    guarded(blocks, cancellation.clone(), deadline)
+                }
+            })
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Reads the records of all splits, using `req` as template for the
+    /// `ReadRecordRequest` of every split. At most `concurrency` splits are
+    /// read at the same time. The batches are returned as soon as they are
+    /// read, so batches of different splits are interleaved; the batches of a
+    /// single split are returned in order. The stream ends after the first
+    /// failed split, including the cancellation of the executor or the expiry
+    /// of the query timeout.
+    pub fn read_all<'a>(
+        &'a self,
+        req: requests::ReadRecordRequest,
+        splits: Vec<models::Split>,
+        concurrency: usize,
+    ) -> BoxStream<'a, Result<SplitBatch>> {
+        let deadline = query_deadline(&self.config);
+        stream::iter(splits.into_iter().enumerate())
+            .map(move |(split_index, split)| {
+                trace!("Reading split {}", split_index);
+                self.read_split(req.with_split(split), deadline)
+                    .enumerate()
+                    .map(move |(batch_index, batch)| {
+                        batch.map(|batch| SplitBatch {
+                            split_index,
+                            batch_index,
+                            batch,
+                        })
+                    })
+            })
+            .flatten_unordered(concurrency.max(1))
+            .scan(false, |failed, res| {
+                if *failed {
+                    return future::ready(None);
+                }
+                *failed = res.is_err();
+                future::ready(Some(res))
+            })
+            .boxed()
+    }
+}
+
+/// Async counterpart of the `UdfClient`. All methods return futures that can
+/// be driven by a tokio runtime. The client can be shared between tasks.
+#[derive(Clone)]
+pub struct AsyncUdfClient {
+    config: Configuration,
+    invoker: Arc<dyn Invoker>,
+    cancellation: CancellationToken,
+    serde_version: SerDeVersion,
+}
+
+impl AsyncUdfClient {
+    /// Instantiates a new AsyncUdfClient object configured with a Configuration
+    /// object. The lambda function is invoked using the AWS Lambda API.
+    pub fn new(c: Configuration) -> Result<Self> {
+        let r = c.region()?;
+        Ok(Self::with_invoker(c, Arc::new(LambdaInvoker::new(r))))
+    }
+
+    /// Instantiates a new AsyncUdfClient object that uses the given `Invoker` to
+    /// call the lambda function.
+    pub fn with_invoker(c: Configuration, invoker: Arc<dyn Invoker>) -> Self {
+        AsyncUdfClient {
+            config: c,
+            invoker,
+            cancellation: CancellationToken::new(),
+            serde_version: SerDeVersion::V1,
+        }
+    }
+
+    /// Uses the token to cancel the invocations of the client.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Returns the token that cancels the invocations of the client.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Uses the wire format of the given version for the requests. The UDF
+    /// lambda may be built with a different SDK than the connector, so the
+    /// version negotiated by `AsyncPlanner::ping` does not apply. Until a
+    /// version is set, the first version is used.
+    pub fn with_serde_version(mut self, version: SerDeVersion) -> Self {
+        self.serde_version = version;
+        self
+    }
+
+    pub fn serde_version(&self) -> SerDeVersion {
+        self.serde_version
+    }
+
+    /// Evaluates the scalar function `method` of the UDF lambda function on the
+    /// rows of the input. The function returns one row per input row with the
+    /// values of the output schema, which usually has a single column.
+    pub async fn invoke_scalar(
+        &self,
+        method: String,
+        input: RecordBatch,
+        output_schema: arrow::datatypes::Schema,
+    ) -> Result<RecordBatch> {
+        if self.config.udf_lambda.is_empty() {
+            return Err(Error::Config("Missing UDF lambda".to_string()));
+        }
+        let req = requests::UserDefinedFunctionRequest::new(
+            models::Block::from_record_batch(input, models::random_allocator_id())?,
+            models::Schema::from_arrow_schema(output_schema)?,
+            method,
+            requests::UserDefinedFunctionRequest::SCALAR.to_string(),
+        );
+        let res: requests::UserDefinedFunctionResponse = invoke(
+            self.invoker.as_ref(),
+            &self.config,
+            &self.cancellation,
+            self.serde_version,
+            &self.config.udf_function(),
+            &req,
+        )
+        .await?;
+        Ok(res.records.into_record_batch())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::invoker::InvocationResult;
+    use crate::retry::RetryPolicy;
+    use crate::spill::InMemoryObjectStore;
+    use crate::test_util::{block_json, result, split_json, RECORDS, SCHEMA};
+    use futures::channel::{mpsc, oneshot};
+    use futures::future::{BoxFuture, FutureExt};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_async_invoke_scalar() {
+        let invoker = |function_name: &str, payload: Vec<u8>| {
+            assert_eq!("udf", function_name);
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            assert_eq!("UserDefinedFunctionRequest", req["@type"]);
+            assert_eq!("SCALAR", req["functionType"]);
+            assert_eq!("echo", req["methodName"]);
+            assert!(req["outputSchema"]["schema"].is_string());
+            result(serde_json::json!({
+                "@type": "UserDefinedFunctionResponse",
+                "records": req["inputRecords"],
+                "methodName": "echo"
+            }))
+        };
+
+        let schema = arrow::datatypes::Schema::new(vec![arrow::datatypes::Field::new(
+            "a",
+            arrow::datatypes::DataType::Int64,
+            true,
+        )]);
+        let input = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(arrow::array::Int64Array::from(vec![
+                Some(1),
+                None,
+                Some(3),
+            ]))],
+        )
+        .unwrap();
+        let c = Configuration::builder()
+            .udf_lambda("udf".to_string())
+            .build()
+            .unwrap();
+        let client = AsyncUdfClient::with_invoker(c, Arc::new(invoker));
+        let output = client
+            .invoke_scalar("echo".to_string(), input.clone(), schema.clone())
+            .await
+            .unwrap();
+        assert_eq!(3, output.num_rows());
+        let values = output
+            .column(0)
+            .as_any()
+            .downcast_ref::<arrow::array::Int64Array>()
+            .unwrap();
+        assert_eq!(3, values.value(2));
+        assert!(values.is_null(1));
+
+        // The UDF lambda is not used by the planner and the executor
+        let planner = AsyncPlanner::with_invoker(c.clone(), Arc::new(invoker));
+        match planner.list_schemas().await {
+            Err(Error::Config(_)) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        let executor =
+            AsyncExecutor::with_invoker(c, Arc::new(invoker), Arc::new(InMemoryObjectStore::new()));
+        match executor
+            .read_records(requests::ReadRecordRequest::default())
+            .await
+        {
+            Err(Error::Config(_)) => {}
+            r => panic!("Unexpected result {:?}", r.map(|b| b.len())),
+        }
+
+        let c = Configuration::builder()
+            .metadata_lambda("metadata".to_string())
+            .record_lambda("records".to_string())
+            .build()
+            .unwrap();
+        let client = AsyncUdfClient::with_invoker(c, Arc::new(invoker));
+        match client
+            .invoke_scalar("echo".to_string(), input, schema)
+            .await
+        {
+            Err(Error::Config(_)) => {}
+            _ => panic!("Expected a configuration error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_all_splits() {
+        let invoker = |_: &str, payload: Vec<u8>| {
+            let req: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+            let (splits, next) = match req["continuationToken"].as_str() {
+                None => (vec![split_json("0"), split_json("1")], Some("1")),
+                _ => (vec![split_json("2")], None),
+            };
+            result(serde_json::json!({
+                "@type": "GetSplitsResponse",
+                "requestType": "GET_SPLITS",
+                "catalogName": "catalog",
+                "splits": splits,
+                "continuationToken": next
+            }))
+        };
+
+        let partitions: models::Block = serde_json::from_value(block_json()).unwrap();
+        let c = Configuration::new("metadata".to_string());
+        let p = AsyncPlanner::with_invoker(c, Arc::new(invoker));
+        let splits: Vec<models::Split> = p
+            .all_splits(
+                "query_id".to_string(),
+                "catalog".to_string(),
+                models::TableName::default(),
+                partitions,
+                vec![],
+                models::Constraints::default(),
+                None,
+            )
+            .try_collect()
+            .await
+            .unwrap();
+        let keys: Vec<&str> = splits.iter().map(|s| s.spill_location().key()).collect();
+        assert_eq!(vec!["0", "1", "2"], keys);
+    }
+
+    #[tokio::test]
+    async fn test_async_read_records_spilled() {
+        let invoker = |_: &str, _: Vec<u8>| {
+            result(serde_json::json!({
+                "@type": "RemoteReadRecordsResponse",
+                "requestType": "READ_RECORDS",
+                "catalogName": "catalog",
+                "schema": { "schema": SCHEMA },
+                "remoteBlocks": [
+                    { "@type": "S3SpillLocation", "bucket": "bucket", "key": "spill", "directory": true }
+                ],
+                "encryptionKey": null
+            }))
+        };
+
+        let store = Arc::new(InMemoryObjectStore::new());
+        store.put("bucket", "spill/split.0", base64::decode(RECORDS).unwrap());
+        store.put("bucket", "spill/split.1", base64::decode(RECORDS).unwrap());
+
+        // The executors share the client and can be used from concurrent tasks
+        let c = Configuration::new("lambda".to_string());
+        let e = Arc::new(AsyncExecutor::with_invoker(c, Arc::new(invoker), store));
+        let tasks = (0..4).map(|_| {
+            let e = e.clone();
+            tokio::spawn(async move {
+                e.read_records(requests::ReadRecordRequest::default())
+                    .await
+                    .map(|batches| batches.len())
+            })
+        });
+        let results = stream::iter(tasks)
+            .then(|task| task)
+            .collect::<Vec<_>>()
+            .await;
+        for r in results {
+            assert_eq!(2, r.unwrap().unwrap());
+        }
+    }
+
+    fn inline_response() -> Result<InvocationResult> {
+        result(serde_json::json!({
+            "@type": "ReadRecordsResponse",
+            "requestType": "READ_RECORDS",
+            "catalogName": "catalog",
+            "records": block_json()
+        }))
+    }
+
+    /// Invoker that returns an inline block after a short delay.
+    struct SlowInvoker;
+
+    impl Invoker for SlowInvoker {
+        fn invoke(&self, _: &str, _: Vec<u8>) -> BoxFuture<'static, Result<InvocationResult>> {
+            async move {
+                tokio::time::delay_for(Duration::from_millis(20)).await;
+                inline_response()
+            }
+            .boxed()
+        }
+    }
+
+    /// Invoker that returns an inline block once the test opens the gate of the
+    /// invocation. The gates are sent to the test when the invocations start.
+    struct GatedInvoker {
+        gates: mpsc::UnboundedSender<oneshot::Sender<()>>,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl Invoker for GatedInvoker {
+        fn invoke(&self, _: &str, _: Vec<u8>) -> BoxFuture<'static, Result<InvocationResult>> {
+            let n = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(n, Ordering::SeqCst);
+            let (gate, opened) = oneshot::channel();
+            self.gates.unbounded_send(gate).unwrap();
+            let in_flight = self.in_flight.clone();
+            async move {
+                opened.await.unwrap();
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                inline_response()
+            }
+            .boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_all_bounded_concurrency() {
+        let (gates, mut started) = mpsc::unbounded();
+        let invoker = Arc::new(GatedInvoker {
+            gates,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: Arc::new(AtomicUsize::new(0)),
+        });
+        let c = Configuration::new("lambda".to_string());
+        let e =
+            AsyncExecutor::with_invoker(c, invoker.clone(), Arc::new(InMemoryObjectStore::new()));
+        let splits: Vec<models::Split> = (0..10).map(|_| models::Split::default()).collect();
+        let task = tokio::spawn(async move {
+            e.read_all(requests::ReadRecordRequest::default(), splits, 3)
+                .try_collect::<Vec<SplitBatch>>()
+                .await
+        });
+
+        // None of the invocations returns before its gate is opened, so the
+        // first three invocations are running at the same time
+        let mut opened = Vec::new();
+        for _ in 0..3 {
+            opened.push(started.next().await.unwrap());
+        }
+        assert_eq!(3, invoker.in_flight.load(Ordering::SeqCst));
+        for gate in opened {
+            gate.send(()).unwrap();
+        }
+        for _ in 3..10 {
+            started.next().await.unwrap().send(()).unwrap();
+        }
+
+        let batches = task.await.unwrap().unwrap();
+        assert_eq!(10, batches.len());
+        assert!(invoker.max_in_flight.load(Ordering::SeqCst) <= 3);
+        let mut split_indexes: Vec<usize> = batches.iter().map(|b| b.split_index).collect();
+        split_indexes.sort();
+        assert_eq!((0..10).collect::<Vec<_>>(), split_indexes);
+        assert!(batches.iter().all(|b| b.batch_index == 0));
+    }
+
+    #[tokio::test]
+    async fn test_read_all_cancelled() {
+        let c = Configuration::new("lambda".to_string());
+        let token = CancellationToken::new();
+        let e = AsyncExecutor::with_invoker(
+            c,
+            Arc::new(SlowInvoker),
+            Arc::new(InMemoryObjectStore::new()),
+        )
+        .with_cancellation(token.clone());
+        let splits: Vec<models::Split> = (0..10).map(|_| models::Split::default()).collect();
+        let mut batches = e.read_all(requests::ReadRecordRequest::default(), splits, 1);
+
+        assert!(batches.next().await.unwrap().is_ok());
+        token.cancel();
+        match batches.next().await {
+            Some(Err(Error::Cancelled)) => {}
+            r => panic!(
+                "Unexpected result {:?}",
+                r.map(|r| r.map(|b| b.split_index))
+            ),
+        }
+        assert!(batches.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_during_backoff() {
+        let token = CancellationToken::new();
+        let invocations = Arc::new(AtomicUsize::new(0));
+        let (cancel, count) = (token.clone(), invocations.clone());
+        let invoker = move |_: &str, _: Vec<u8>| {
+            count.fetch_add(1, Ordering::SeqCst);
+            cancel.cancel();
+            Ok(InvocationResult {
+                payload:
+                    br#"{"errorMessage": "Rate exceeded", "errorType": "ThrottlingException"}"#
+                        .to_vec(),
+                function_error: Some("Unhandled".to_string()),
+            })
+        };
+
+        let c = Configuration::builder()
+            .lambda("metadata".to_string())
+            .retry_policy(RetryPolicy::default().base_delay(Duration::from_millis(1)))
+            .build()
+            .unwrap();
+        let p = AsyncPlanner::with_invoker(c, Arc::new(invoker)).with_cancellation(token);
+        match p.list_schemas().await {
+            Err(Error::Cancelled) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert_eq!(1, invocations.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts() {
+        let invoker = Arc::new(SlowInvoker);
+        let splits: Vec<models::Split> = (0..10).map(|_| models::Split::default()).collect();
+
+        let c = Configuration::builder()
+            .lambda("lambda".to_string())
+            .query_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let e =
+            AsyncExecutor::with_invoker(c, invoker.clone(), Arc::new(InMemoryObjectStore::new()));
+        let res: Result<Vec<SplitBatch>> = e
+            .read_all(requests::ReadRecordRequest::default(), splits, 1)
+            .try_collect()
+            .await;
+        match res {
+            Err(Error::Timeout(_)) => {}
+            r => panic!("Unexpected result {:?}", r.map(|b| b.len())),
+        }
+
+        let c = Configuration::builder()
+            .lambda("lambda".to_string())
+            .call_timeout(Duration::from_millis(5))
+            .build()
+            .unwrap();
+        let e = AsyncExecutor::with_invoker(c, invoker, Arc::new(InMemoryObjectStore::new()));
+        match e.read_records(requests::ReadRecordRequest::default()).await {
+            Err(Error::Timeout(_)) => {}
+            r => panic!("Unexpected result {:?}", r.map(|b| b.len())),
+        }
+    }
+}
@@ -0,0 +1,478 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::arn::LambdaArn;
+use super::error::{Error, Result};
+use super::retry::RetryPolicy;
+use rusoto_core::Region;
+use std::time::Duration;
+
+/// Region used if no region is configured.
+const DEFAULT_REGION: &str = "us-east-1";
+/// Default maximum size of a block written by the lambda function.
+const DEFAULT_MAX_BLOCK_SIZE: i64 = 16000000;
+/// Default maximum size of a block returned inline by the lambda function.
+const DEFAULT_MAX_INLINE_BLOCK_SIZE: i64 = 5242880;
+
+/// Configuration of the lambda functions used by the `Planner`, the
+/// `Executor` and the `UdfClient`. Use the `ConfigurationBuilder` to create a validated
+/// configuration. Each client only needs its own lambda function: the planner
+/// the metadata lambda, the executor the record lambda and the UDF client the
+/// UDF lambda. The requests of a client fail if its lambda is missing.
+///
+/// If the lambda functions are given as ARNs, the region, account and
+/// qualifier are inferred from the ARNs unless they are set explicitly.
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    pub(crate) record_lambda: String,
+    pub(crate) metadata_lambda: String,
+    pub(crate) udf_lambda: String,
+    pub(crate) region: Option<String>,
+    pub(crate) endpoint: Option<String>,
+    pub(crate) qualifier: Option<String>,
+    pub(crate) max_block_size: i64,
+    pub(crate) max_inline_block_size: i64,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) call_timeout: Option<Duration>,
+    pub(crate) query_timeout: Option<Duration>,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Configuration {
+            record_lambda: String::new(),
+            metadata_lambda: String::new(),
+            udf_lambda: String::new(),
+            region: None,
+            endpoint: None,
+            qualifier: None,
+            max_block_size: DEFAULT_MAX_BLOCK_SIZE,
+            max_inline_block_size: DEFAULT_MAX_INLINE_BLOCK_SIZE,
+            retry_policy: RetryPolicy::default(),
+            call_timeout: None,
+            query_timeout: None,
+        }
+    }
+}
+
+impl Configuration {
+    /// Creates a configuration using the same lambda function for metadata,
+    /// record and user defined function requests.
+    pub fn new(lambda: String) -> Configuration {
+        Configuration {
+            record_lambda: lambda.clone(),
+            metadata_lambda: lambda.clone(),
+            udf_lambda: lambda,
+            ..Default::default()
+        }
+    }
+
+    pub fn builder() -> ConfigurationBuilder {
+        ConfigurationBuilder::default()
+    }
+
+    /// Returns the parsed ARN of the metadata lambda function, if it was
+    /// configured as ARN.
+    pub fn metadata_arn(&self) -> Result<Option<LambdaArn>> {
+        parse_arn(&self.metadata_lambda)
+    }
+
+    /// Returns the parsed ARN of the record lambda function, if it was
+    /// configured as ARN.
+    pub fn record_arn(&self) -> Result<Option<LambdaArn>> {
+        parse_arn(&self.record_lambda)
+    }
+
+    /// Returns the parsed ARN of the UDF lambda function, if it was configured
+    /// as ARN.
+    pub fn udf_arn(&self) -> Result<Option<LambdaArn>> {
+        parse_arn(&self.udf_lambda)
+    }
+
+    /// Returns the ARNs of all lambda functions that were configured as ARN.
+    fn arns(&self) -> Result<Vec<LambdaArn>> {
+        let arns = vec![self.metadata_arn()?, self.record_arn()?, self.udf_arn()?];
+        Ok(arns.into_iter().flatten().collect())
+    }
+
+    /// Returns the name of the region the lambda functions are invoked in. An
+    /// explicitly configured region takes precedence over the region of the
+    /// ARNs. It is an error if the ARNs of the functions disagree on the
+    /// region and no region is configured.
+    pub fn region_name(&self) -> Result<String> {
+        if let Some(region) = &self.region {
+            return Ok(region.clone());
+        }
+
+        let arns = self.arns()?;
+        match arns.split_first() {
+            Some((first, rest)) => match rest.iter().find(|a| a.region != first.region) {
+                Some(other) => Err(Error::Config(format!(
+                    "The lambda functions are in the regions `{}` and `{}`",
+                    first.region, other.region
+                ))),
+                None => Ok(first.region.clone()),
+            },
+            None => Ok(DEFAULT_REGION.to_string()),
+        }
+    }
+
+    /// Returns the region the lambda functions are invoked in. If an endpoint
+    /// is configured, all requests are sent to this endpoint instead.
+    pub fn region(&self) -> Result<Region> {
+        let region = self.region_name()?;
+        match &self.endpoint {
+            Some(endpoint) => Ok(Region::Custom {
+                name: region,
+                endpoint: endpoint.clone(),
+            }),
+            None => region
+                .parse()
+                .map_err(|_| Error::Config(format!("Invalid region `{}`", region))),
+        }
+    }
+
+    /// Returns the account of the lambda functions, if known from the ARNs.
+    pub fn account(&self) -> Result<Option<String>> {
+        Ok(self.arns()?.into_iter().next().map(|arn| arn.account))
+    }
+
+    /// Returns the name of the metadata lambda function including the qualifier.
+    pub fn metadata_function(&self) -> String {
+        self.qualified(&self.metadata_lambda)
+    }
+
+    /// Returns the name of the record lambda function including the qualifier.
+    pub fn record_function(&self) -> String {
+        self.qualified(&self.record_lambda)
+    }
+
+    /// Returns the name of the UDF lambda function including the qualifier.
+    pub fn udf_function(&self) -> String {
+        self.qualified(&self.udf_lambda)
+    }
+
+    /// Lambda accepts the version or alias to invoke as suffix of the function
+    /// name. If the function is an ARN that already contains a qualifier, it is
+    /// used as is.
+    fn qualified(&self, function: &str) -> String {
+        let arn_qualified = match parse_arn(function) {
+            Ok(Some(arn)) => arn.qualifier.is_some(),
+            _ => false,
+        };
+        match &self.qualifier {
+            Some(qualifier) if !arn_qualified => format!("{}:{}", function, qualifier),
+            _ => function.to_string(),
+        }
+    }
+
+    /// Checks that the qualifiers of the ARNs match the configured qualifier.
+    fn validate_qualifiers(&self) -> Result<()> {
+        for arn in &self.arns()? {
+            match (arn.qualifier.as_ref(), &self.qualifier) {
+                (Some(a), Some(q)) if a != q => {
+                    return Err(Error::Config(format!(
+                        "The qualifier `{}` does not match the qualifier `{}` of the ARN",
+                        q, a
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn max_block_size(&self) -> i64 {
+        self.max_block_size
+    }
+
+    pub fn max_inline_block_size(&self) -> i64 {
+        self.max_inline_block_size
+    }
+
+    /// Returns the policy for retrying failed invocations of the metadata and
+    /// record lambda functions.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Returns the maximum duration of a single invocation of a lambda function.
+    pub fn call_timeout(&self) -> Option<Duration> {
+        self.call_timeout
+    }
+
+    /// Returns the maximum duration of a query, i.e. of fetching all splits of a
+    /// table layout or reading the records of all splits.
+    pub fn query_timeout(&self) -> Option<Duration> {
+        self.query_timeout
+    }
+}
+
+/// Parses the function as ARN if it is not a plain function name.
+fn parse_arn(function: &str) -> Result<Option<LambdaArn>> {
+    if LambdaArn::is_arn(function) {
+        Ok(Some(function.parse()?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Builder to create a validated `Configuration`.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigurationBuilder {
+    config: Configuration,
+}
+
+impl ConfigurationBuilder {
+    /// Uses the same lambda function for metadata, record and user defined
+    /// function requests.
+    pub fn lambda(mut self, lambda: String) -> Self {
+        self.config.record_lambda = lambda.clone();
+        self.config.metadata_lambda = lambda.clone();
+        self.config.udf_lambda = lambda;
+        self
+    }
+
+    pub fn metadata_lambda(mut self, lambda: String) -> Self {
+        self.config.metadata_lambda = lambda;
+        self
+    }
+
+    pub fn record_lambda(mut self, lambda: String) -> Self {
+        self.config.record_lambda = lambda;
+        self
+    }
+
+    /// Sets the lambda function invoked by the `UdfClient`. Athena UDF lambda
+    /// functions are often deployed separately from the connectors.
+    pub fn udf_lambda(mut self, lambda: String) -> Self {
+        self.config.udf_lambda = lambda;
+        self
+    }
+
+    /// Sets the region explicitly instead of inferring it from the ARNs.
+    pub fn region(mut self, region: String) -> Self {
+        self.config.region = Some(region);
+        self
+    }
+
+    /// Sends all requests to the given endpoint URL instead of the AWS endpoint
+    /// of the region, e.g. to use a local stand-in.
+    pub fn endpoint(mut self, endpoint: String) -> Self {
+        self.config.endpoint = Some(endpoint);
+        self
+    }
+
+    /// Sets the version or alias of the lambda functions to invoke.
+    pub fn qualifier(mut self, qualifier: String) -> Self {
+        self.config.qualifier = Some(qualifier);
+        self
+    }
+
+    pub fn max_block_size(mut self, size: i64) -> Self {
+        self.config.max_block_size = size;
+        self
+    }
+
+    pub fn max_inline_block_size(mut self, size: i64) -> Self {
+        self.config.max_inline_block_size = size;
+        self
+    }
+
+    /// Sets the policy for retrying failed invocations.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.config.retry_policy = policy;
+        self
+    }
+
+    /// Bounds the duration of a single invocation. The lambda function is not
+    /// stopped when the timeout expires, but its result is discarded.
+    pub fn call_timeout(mut self, timeout: Duration) -> Self {
+        self.config.call_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds the duration of fetching all splits of a table layout and of
+    /// reading the records of all splits.
+    pub fn query_timeout(mut self, timeout: Duration) -> Self {
+        self.config.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Validates the settings and creates the `Configuration`.
+    pub fn build(self) -> Result<Configuration> {
+        let c = self.config;
+        if let Some(qualifier) = &c.qualifier {
+            if qualifier.is_empty() || qualifier.contains(':') {
+                return Err(Error::Config(format!("Invalid qualifier `{}`", qualifier)));
+            }
+        }
+        if c.max_block_size <= 0 || c.max_inline_block_size <= 0 {
+            return Err(Error::Config("Block sizes must be positive".to_string()));
+        }
+        if c.max_inline_block_size > c.max_block_size {
+            return Err(Error::Config(
+                "The max inline block size must not exceed the max block size".to_string(),
+            ));
+        }
+        c.validate_qualifiers()?;
+        c.region()?;
+        Ok(c)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn default_test() {
+        let c = Configuration::default();
+        assert!(c.record_lambda.is_empty());
+        assert!(c.metadata_lambda.is_empty());
+    }
+
+    #[test]
+    fn test_config_setup() {
+        let c = Configuration::new("this-is-my-arn".to_string());
+        assert_eq!("this-is-my-arn".to_string(), c.record_lambda);
+        assert_eq!(c.metadata_lambda, c.record_lambda);
+    }
+
+    #[test]
+    fn test_builder() {
+        let c = Configuration::builder()
+            .metadata_lambda("metadata".to_string())
+            .record_lambda("records".to_string())
+            .region("eu-west-1".to_string())
+            .qualifier("prod".to_string())
+            .max_block_size(1000)
+            .max_inline_block_size(100)
+            .build()
+            .unwrap();
+        assert_eq!("metadata:prod", c.metadata_function());
+        assert_eq!("records:prod", c.record_function());
+        assert_eq!("", c.udf_lambda);
+        assert_eq!(Region::EuWest1, c.region().unwrap());
+        assert_eq!(1000, c.max_block_size());
+        assert_eq!(100, c.max_inline_block_size());
+    }
+
+    #[test]
+    fn test_builder_endpoint() {
+        let c = Configuration::builder()
+            .lambda("lambda".to_string())
+            .region("local".to_string())
+            .endpoint("http://localhost:4574".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(
+            Region::Custom {
+                name: "local".to_string(),
+                endpoint: "http://localhost:4574".to_string()
+            },
+            c.region().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_region_from_arn() {
+        let c = Configuration::new("arn:aws:lambda:eu-central-1:123:function:cwtest".to_string());
+        assert_eq!(Region::EuCentral1, c.region().unwrap());
+        assert_eq!(Some("123".to_string()), c.account().unwrap());
+
+        let c = Configuration::new("cwtest".to_string());
+        assert_eq!(Region::UsEast1, c.region().unwrap());
+        assert!(c.account().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_udf_lambda() {
+        let c = Configuration::builder()
+            .udf_lambda("arn:aws:lambda:eu-central-1:123:function:udf".to_string())
+            .qualifier("prod".to_string())
+            .build()
+            .unwrap();
+        assert!(c.metadata_lambda.is_empty());
+        assert_eq!(
+            "arn:aws:lambda:eu-central-1:123:function:udf:prod",
+            c.udf_function()
+        );
+        assert_eq!(Region::EuCentral1, c.region().unwrap());
+
+        assert!(Configuration::builder()
+            .lambda("arn:aws:lambda:eu-west-1:123:function:cwtest".to_string())
+            .udf_lambda("arn:aws:lambda:eu-central-1:123:function:udf".to_string())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_region_mismatch() {
+        let builder = Configuration::builder()
+            .metadata_lambda("arn:aws:lambda:eu-central-1:123:function:cwtest".to_string())
+            .record_lambda("arn:aws:lambda:eu-west-1:123:function:cwtest".to_string());
+        assert!(builder.clone().build().is_err());
+
+        let c = builder.region("eu-west-1".to_string()).build().unwrap();
+        assert_eq!(Region::EuWest1, c.region().unwrap());
+    }
+
+    #[test]
+    fn test_qualifier_from_arn() {
+        let c = Configuration::builder()
+            .lambda("arn:aws:lambda:eu-central-1:123:function:cwtest:prod".to_string())
+            .build()
+            .unwrap();
+        assert_eq!(
+            "arn:aws:lambda:eu-central-1:123:function:cwtest:prod",
+            c.metadata_function()
+        );
+
+        assert!(Configuration::builder()
+            .lambda("arn:aws:lambda:eu-central-1:123:function:cwtest:prod".to_string())
+            .qualifier("dev".to_string())
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        // The lambda functions are validated by the clients using them
+        assert!(Configuration::builder()
+            .metadata_lambda("metadata".to_string())
+            .build()
+            .is_ok());
+        assert!(Configuration::builder()
+            .lambda("lambda".to_string())
+            .region("moon-east-1".to_string())
+            .build()
+            .is_err());
+        assert!(Configuration::builder()
+            .lambda("lambda".to_string())
+            .max_block_size(10)
+            .max_inline_block_size(100)
+            .build()
+            .is_err());
+        assert!(Configuration::builder()
+            .lambda("lambda".to_string())
+            .qualifier("".to_string())
+            .build()
+            .is_err());
+    }
+}
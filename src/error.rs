@@ -0,0 +1,180 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use arrow::error::ArrowError;
+use serde::Deserialize;
+use std::fmt;
+
+/// Exception raised by the connector inside the lambda function, as reported
+/// in the payload of a failed invocation.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectorError {
+    /// The class name of the exception, e.g. `java.lang.RuntimeException`.
+    #[serde(default)]
+    pub error_type: String,
+    #[serde(rename = "errorMessage", default)]
+    pub message: String,
+    #[serde(default)]
+    pub stack_trace: Vec<String>,
+    /// The exception that caused this exception, if any.
+    #[serde(default)]
+    pub cause: Option<Box<ConnectorError>>,
+}
+
+impl ConnectorError {
+    /// Decodes the payload of a failed invocation. If the payload is not a
+    /// serialized exception, the raw payload is used as message and the
+    /// `function_error` reported by Lambda as error type.
+    pub fn from_payload(function_error: &str, payload: &[u8]) -> Self {
+        match serde_json::from_slice::<ConnectorError>(payload) {
+            Ok(e) if !e.error_type.is_empty() || !e.message.is_empty() => e,
+            _ => ConnectorError {
+                error_type: function_error.to_string(),
+                message: String::from_utf8_lossy(payload).to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl fmt::Display for ConnectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type, self.message)?;
+        if let Some(cause) = &self.cause {
+            write!(f, " (caused by {})", cause)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error type for all fallible operations of the federation client.
+#[derive(Debug)]
+pub enum Error {
+    /// The lambda function could not be invoked.
+    Transport(String),
+    /// The lambda function could not be invoked due to a transient failure,
+    /// e.g. throttling. The invocation may succeed if it is retried.
+    Transient(String),
+    /// The lambda function was invoked, but the connector raised an error.
+    Connector(ConnectorError),
+    /// A request or response could not be (de)serialized.
+    Protocol(String),
+    /// An Arrow IPC message could not be decoded.
+    Arrow(String),
+    /// A spilled block could not be read from the object store.
+    Spill(String),
+    /// A spilled block could not be encrypted or decrypted.
+    Encryption(String),
+    /// The configuration of the client is invalid.
+    Config(String),
+    /// A predicate cannot be translated into constraints.
+    Predicate(String),
+    /// The connector does not support a feature required by the request.
+    Unsupported(String),
+    /// An invocation or query did not finish within its timeout.
+    Timeout(String),
+    /// The query was cancelled using its `CancellationToken`.
+    Cancelled,
+}
+
+/// Result type used throughout the federation client.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Transport(msg) => write!(f, "Transport error: {}", msg),
+            Error::Transient(msg) => write!(f, "Transient error: {}", msg),
+            Error::Connector(e) => write!(f, "Connector error: {}", e),
+            Error::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            Error::Arrow(msg) => write!(f, "Arrow error: {}", msg),
+            Error::Spill(msg) => write!(f, "Spill error: {}", msg),
+            Error::Encryption(msg) => write!(f, "Encryption error: {}", msg),
+            Error::Config(msg) => write!(f, "Configuration error: {}", msg),
+            Error::Predicate(msg) => write!(f, "Invalid predicate: {}", msg),
+            Error::Unsupported(msg) => write!(f, "Unsupported by the connector: {}", msg),
+            Error::Timeout(msg) => write!(f, "Timeout: {}", msg),
+            Error::Cancelled => write!(f, "The query was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(e: std::str::Utf8Error) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(e: base64::DecodeError) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+impl From<ArrowError> for Error {
+    fn from(e: ArrowError) -> Self {
+        Error::Arrow(format!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_connector_error() {
+        let payload = br#"{
+            "errorMessage": "Table not found",
+            "errorType": "java.lang.RuntimeException",
+            "stackTrace": [
+                "com.amazonaws.athena.connectors.cloudwatch.CloudwatchMetadataHandler.doGetTable(CloudwatchMetadataHandler.java:201)"
+            ],
+            "cause": {
+                "errorMessage": "Log group does not exist",
+                "errorType": "com.amazonaws.services.logs.model.ResourceNotFoundException",
+                "stackTrace": []
+            }
+        }"#;
+
+        let e = ConnectorError::from_payload("Unhandled", payload);
+        assert_eq!("java.lang.RuntimeException", e.error_type);
+        assert_eq!("Table not found", e.message);
+        assert_eq!(1, e.stack_trace.len());
+        assert_eq!(
+            "Log group does not exist",
+            e.cause.as_ref().unwrap().message
+        );
+    }
+
+    #[test]
+    fn test_connector_error_raw_payload() {
+        let e = ConnectorError::from_payload("Unhandled", b"Task timed out");
+        assert_eq!("Unhandled", e.error_type);
+        assert_eq!("Task timed out", e.message);
+        assert!(e.stack_trace.is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::error::{Error, Result};
+use bytes::Bytes;
+use futures::future::{self, BoxFuture, FutureExt};
+use rusoto_core::{Region, RusotoError};
+use rusoto_lambda::{InvocationRequest, InvokeError, Lambda, LambdaClient};
+
+/// Result of a single invocation of a lambda function.
+#[derive(Debug, Default, Clone)]
+pub struct InvocationResult {
+    /// The payload returned by the function.
+    pub payload: Vec<u8>,
+    /// Set if the function failed. In this case the payload contains the
+    /// serialized error instead of the response.
+    pub function_error: Option<String>,
+}
+
+/// The `Invoker` is the transport used by the `Planner` and the `Executor`
+/// to send the serialized requests to the lambda functions. Besides the
+/// `LambdaInvoker`, it allows to plug in local stand-ins of the connectors.
+///
+/// The trait is implemented for all closures with a matching signature. The
+/// closures are called synchronously and their result is returned as a ready
+/// future.
+pub trait Invoker: Send + Sync {
+    /// Invokes the function with the given name and payload. Errors raised by
+    /// the function itself are not returned as `Err`, but via the
+    /// `function_error` of the result.
+    fn invoke(
+        &self,
+        function_name: &str,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'static, Result<InvocationResult>>;
+}
+
+impl<F> Invoker for F
+where
+    F: Fn(&str, Vec<u8>) -> Result<InvocationResult> + Send + Sync,
+{
+    fn invoke(
+        &self,
+        function_name: &str,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'static, Result<InvocationResult>> {
+        future::ready(self(function_name, payload)).boxed()
+    }
+}
+
+/// `Invoker` implementation calling the functions using the AWS Lambda API.
+#[derive(Clone)]
+pub struct LambdaInvoker {
+    client: LambdaClient,
+}
+
+impl LambdaInvoker {
+    pub fn new(region: Region) -> Self {
+        LambdaInvoker {
+            client: LambdaClient::new(region),
+        }
+    }
+}
+
+impl Invoker for LambdaInvoker {
+    fn invoke(
+        &self,
+        function_name: &str,
+        payload: Vec<u8>,
+    ) -> BoxFuture<'static, Result<InvocationResult>> {
+        // Setup the request
+        let mut lambda_fun = InvocationRequest::default();
+        lambda_fun.function_name = function_name.to_string();
+        lambda_fun.payload = Some(Bytes::from(payload));
+
+        // The request is sent on the first poll, so the future can be created
+        // outside of a runtime.
+        let client = self.client.clone();
+        async move {
+            let result = client.invoke(lambda_fun).await.map_err(invoke_error)?;
+            Ok(InvocationResult {
+                payload: result.payload.map(|p| p.to_vec()).unwrap_or_default(),
+                function_error: result.function_error,
+            })
+        }
+        .boxed()
+    }
+}
+
+/// Maps the errors of the Lambda API. Throttling, failures of the Lambda
+/// service and network failures are reported as transient errors.
+fn invoke_error(e: RusotoError<InvokeError>) -> Error {
+    match e {
+        RusotoError::Service(InvokeError::TooManyRequests(msg))
+        | RusotoError::Service(InvokeError::Service(msg))
+        | RusotoError::Service(InvokeError::EC2Throttled(msg)) => Error::Transient(msg),
+        RusotoError::HttpDispatch(e) => Error::Transient(e.to_string()),
+        e => Error::Transport(e.to_string()),
+    }
+}
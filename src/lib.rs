@@ -21,9 +21,27 @@ extern crate log;
 
 // Rexport the models module
 mod api;
+pub mod arn;
+mod async_api;
+pub mod cancel;
+mod config;
+pub mod error;
+pub mod invoker;
 pub mod models;
+pub mod predicate;
 pub mod requests;
+pub mod retry;
+pub mod spill;
+#[cfg(test)]
+pub(crate) mod test_util;
 
-pub use self::api::Configuration;
-pub use self::api::Planner;
 pub use self::api::Executor;
+pub use self::api::Planner;
+pub use self::api::SplitStream;
+pub use self::api::UdfClient;
+pub use self::async_api::{
+    AsyncExecutor, AsyncPlanner, AsyncUdfClient, PassthroughQuery, SplitBatch,
+};
+pub use self::cancel::CancellationToken;
+pub use self::config::{Configuration, ConfigurationBuilder};
+pub use self::error::{ConnectorError, Error, Result};
@@ -15,14 +15,20 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use super::error::{self, Error};
+use super::predicate::ConstraintsBuilder;
 use base64;
+use ring::aead;
+use ring::rand::{SecureRandom, SystemRandom};
 use std::collections::HashMap;
 use std::default::Default;
 
 use arrow;
+use arrow::datatypes::DataType;
 use arrow::ipc;
 use arrow::ipc::file::reader as rr;
 use arrow::ipc::gen::Message::MessageHeader;
+use arrow::ipc::writer::StreamWriter;
 use arrow::record_batch::RecordBatch;
 
 use serde;
@@ -39,7 +45,7 @@ use std::sync::Arc;
 /// Base class referring to the federated identity. This is normally populated
 /// by Athena using the Access Key and the account number.
 //#[derive(Debug)]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FederatedIdentity {
     id: String,
@@ -58,6 +64,50 @@ impl Default for FederatedIdentity {
     }
 }
 
+/// Version of the wire format negotiated with a connector using a
+/// `PingRequest`. Connectors built with an older SDK only understand the
+/// format of their version, so requests are downgraded before they are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SerDeVersion(u32);
+
+impl SerDeVersion {
+    /// Blocks are written in the legacy Arrow IPC format without the
+    /// continuation marker.
+    pub const V1: SerDeVersion = SerDeVersion(1);
+    /// Blocks are written in the Arrow IPC format of Arrow 0.15.0 and up.
+    pub const V2: SerDeVersion = SerDeVersion(2);
+    /// Constraints may contain federation expressions.
+    pub const V3: SerDeVersion = SerDeVersion(3);
+    /// Constraints may contain an ORDER BY clause and a limit.
+    pub const V4: SerDeVersion = SerDeVersion(4);
+    /// Requests may contain the arguments of a query passthrough function.
+    pub const V5: SerDeVersion = SerDeVersion(5);
+    /// The latest version supported by the client.
+    pub const LATEST: SerDeVersion = SerDeVersion::V5;
+
+    pub fn new(version: u32) -> Self {
+        SerDeVersion(version)
+    }
+
+    pub fn value(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the version to use with a connector supporting the given
+    /// version, i.e. the lower of the two versions.
+    pub fn negotiate(connector: SerDeVersion) -> Self {
+        connector.min(SerDeVersion::LATEST)
+    }
+}
+
+impl Default for SerDeVersion {
+    /// Connectors that do not report a version use the first version.
+    fn default() -> Self {
+        SerDeVersion::V1
+    }
+}
+
 /// Value struct storing information about the table name.
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -93,44 +143,45 @@ impl Schema {
         }
     }
 
+    /// Encodes the Arrow schema as a Schema IPC message, e.g. for the output
+    /// schema of a user defined function.
+    pub fn from_arrow_schema(schema: arrow::datatypes::Schema) -> error::Result<Self> {
+        let mut buf = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut buf, &schema)?;
+            writer.finish()?;
+        }
+
+        let messages = ipc_messages(&buf)?;
+        let message = messages
+            .first()
+            .ok_or_else(|| Error::Arrow("Missing Schema message".to_string()))?;
+        Ok(Schema {
+            schema: base64::encode(message),
+            arrow_schema: Some(schema),
+        })
+    }
+
     /// Returns the arrow Schema object for the column. If the schema has not yet
     /// been decoded, it will decode it from the binary string representation.
     /// TOOD(magrund) We should implement a deserialize_with function instesad of this wrapper.
-    pub fn get_schema(&mut self) -> Option<arrow::datatypes::Schema> {
+    pub fn get_schema(&mut self) -> error::Result<arrow::datatypes::Schema> {
         trace!("Deserializing schema");
-        if self.arrow_schema.is_some() {
-            return self.arrow_schema.clone();
-        }
-
-        let res = base64::decode(&self.schema);
-        if res.is_err() {
-            error!("Could not deserialize base64 Schema string");
-            return None;
-        }
-        let schema_str_decoded = res.unwrap();
-        // Now, try to decode the Arrow object. If we have a message written by
-        // Arrow 0.15.0 and up, we might have to add additional 4 bytes padding.
-
-        let mut fbs = ipc::get_size_prefixed_root_as_message(&schema_str_decoded);
-        if fbs.header_type() == MessageHeader::NONE {
-            fbs = ipc::get_size_prefixed_root_as_message(&schema_str_decoded[4..]);
+        if let Some(schema) = &self.arrow_schema {
+            return Ok(schema.clone());
         }
 
-        if fbs.header_type() != MessageHeader::Schema {
-            error!("Could not parse Schema flatbuffer message");
-            return None;
-        }
-
-        let schema_fbs = fbs.header_as_schema().unwrap();
-        self.arrow_schema = Some(ipc::convert::fb_to_schema(schema_fbs));
-        self.arrow_schema.clone()
+        let schema_str_decoded = base64::decode(&self.schema)?;
+        let schema = decode_schema(&schema_str_decoded)?;
+        self.arrow_schema = Some(schema.clone());
+        Ok(schema)
     }
 }
 
 /// This struct represents the block as transmitted over the wire from
 /// the SDK. This struct is used to cache the serialized representation
 /// to make it easy to send it back to the lambda functions.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct SerializedBlock {
     schema: String,
     records: String,
@@ -138,7 +189,7 @@ struct SerializedBlock {
 }
 
 /// This is a value container for an Arrow schema object.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Block {
     /// Holds a RecordBatch of Arrow values.
     records: RecordBatch,
@@ -161,6 +212,51 @@ impl Block {
             serialized,
         }
     }
+
+    /// Encodes the record batch into a block, e.g. to send partitions, values of
+    /// constraints or the input of a user defined function to a connector. The
+    /// batch is written as an Arrow IPC stream, which is then split into its
+    /// Schema and RecordBatch messages. Both messages start with the continuation
+    /// marker like the messages written by the SDK. Use `random_allocator_id`
+    /// if the connector does not expect a specific allocator.
+    pub fn from_record_batch(batch: RecordBatch, allocator_id: String) -> error::Result<Block> {
+        let schema = batch.schema().clone();
+        let mut buf = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut buf, &schema)?;
+            writer.write(&batch)?;
+            writer.finish()?;
+        }
+
+        let messages = ipc_messages(&buf)?;
+        if messages.len() != 2 {
+            return Err(Error::Arrow(format!(
+                "Expected a Schema and a RecordBatch message, got {} messages",
+                messages.len()
+            )));
+        }
+        Ok(Block::new(
+            batch,
+            base64::encode(messages[0]),
+            base64::encode(messages[1]),
+            allocator_id,
+        ))
+    }
+
+    /// Returns the identifier of the allocator of the block.
+    pub fn allocator_id(&self) -> &str {
+        &self.serialized.a_id
+    }
+
+    /// Returns a reference to the decoded Arrow values of the block.
+    pub fn record_batch(&self) -> &RecordBatch {
+        &self.records
+    }
+
+    /// Consumes the block and returns the decoded Arrow values.
+    pub fn into_record_batch(self) -> RecordBatch {
+        self.records
+    }
 }
 
 /// Helper convert a serde_json::Value as a String into a binary value.
@@ -176,6 +272,132 @@ fn decode_value(v: Option<&Value>) -> Option<Vec<u8>> {
     }
 }
 
+/// Marker written by Arrow 0.15.0 and up in front of the size prefix of every
+/// IPC message.
+const CONTINUATION_MARKER: [u8; 4] = [0xff; 4];
+
+/// Returns the size prefixed flatbuffer of an Arrow IPC message. Messages written
+/// by Arrow 0.15.0 and up are prefixed with an additional continuation marker,
+/// which is skipped.
+fn ipc_message(buf: &[u8]) -> error::Result<ipc::Message> {
+    let buf = if buf.starts_with(&CONTINUATION_MARKER) {
+        &buf[4..]
+    } else {
+        buf
+    };
+
+    if buf.len() < 4 {
+        return Err(Error::Arrow("Truncated IPC message".to_string()));
+    }
+    let len = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if buf.len() < len + 4 {
+        return Err(Error::Arrow("Truncated IPC message".to_string()));
+    }
+    Ok(ipc::get_size_prefixed_root_as_message(buf))
+}
+
+/// Splits an Arrow IPC stream into its messages. Each message includes its size
+/// prefix and its body. The end of stream marker is not returned.
+fn ipc_messages(buf: &[u8]) -> error::Result<Vec<&[u8]>> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let rest = &buf[offset..];
+        let prefix = if rest.starts_with(&CONTINUATION_MARKER) {
+            8
+        } else {
+            4
+        };
+        if rest.len() < prefix {
+            return Err(Error::Arrow("Truncated IPC stream".to_string()));
+        }
+        let p = &rest[prefix - 4..prefix];
+        let len = u32::from_le_bytes([p[0], p[1], p[2], p[3]]) as usize;
+        if len == 0 {
+            break;
+        }
+
+        let end = prefix + len + ipc_message(rest)?.bodyLength() as usize;
+        if rest.len() < end {
+            return Err(Error::Arrow("Truncated IPC stream".to_string()));
+        }
+        messages.push(&rest[..end]);
+        offset += end;
+    }
+    Ok(messages)
+}
+
+/// Returns a random identifier for the allocator of a block. The SDK uses
+/// UUIDs to identify the allocators of blocks.
+pub fn random_allocator_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Converts the base64 encoded Arrow IPC message into the format of the given
+/// version by adding or removing the continuation marker.
+pub(crate) fn ipc_for_version(message: &str, version: SerDeVersion) -> error::Result<String> {
+    let buf = base64::decode(message)?;
+    let has_marker = buf.starts_with(&CONTINUATION_MARKER);
+    let buf = match (version >= SerDeVersion::V2, has_marker) {
+        (true, false) => [&CONTINUATION_MARKER[..], &buf].concat(),
+        (false, true) => buf[4..].to_vec(),
+        _ => return Ok(message.to_string()),
+    };
+    Ok(base64::encode(&buf))
+}
+
+/// Decodes an Arrow IPC Schema message into an Arrow schema.
+fn decode_schema(schema: &[u8]) -> error::Result<arrow::datatypes::Schema> {
+    let fbs = ipc_message(schema)?;
+    if fbs.header_type() != MessageHeader::Schema {
+        return Err(Error::Arrow(
+            "Could not parse Schema flatbuffer message".to_string(),
+        ));
+    }
+
+    let schema_fbs = fbs
+        .header_as_schema()
+        .ok_or_else(|| Error::Arrow("Missing Schema header".to_string()))?;
+    Ok(ipc::convert::fb_to_schema(schema_fbs))
+}
+
+/// Decodes a single Arrow IPC RecordBatch message as written by the SDK into a
+/// `RecordBatch` using the given schema. The same encoding is used for inline
+/// blocks and for spilled blocks.
+pub(crate) fn decode_record_batch(
+    records: &[u8],
+    schema: Arc<arrow::datatypes::Schema>,
+) -> error::Result<RecordBatch> {
+    let fbs_records = ipc_message(records)?;
+    if fbs_records.header_type() != MessageHeader::RecordBatch {
+        return Err(Error::Arrow(
+            "Could not parse RecordBatch flatbuffer message".to_string(),
+        ));
+    }
+
+    let body_length = fbs_records.bodyLength() as usize;
+    if records.len() < body_length {
+        return Err(Error::Arrow("Truncated RecordBatch body".to_string()));
+    }
+    let fbs_records = fbs_records
+        .header_as_record_batch()
+        .ok_or_else(|| Error::Arrow("Missing RecordBatch header".to_string()))?;
+    // Read fom the record batch
+    rr::read_record_batch(&records[records.len() - body_length..], fbs_records, schema)?
+        .ok_or_else(|| Error::Arrow("Empty RecordBatch message".to_string()))
+}
+
 impl<'de> Deserialize<'de> for Block {
     /// Custom implementation to deserialize a Block from a given JSON record. First, we
     /// extract the JSON string values from the known keys, then we convert them to binary
@@ -186,69 +408,41 @@ impl<'de> Deserialize<'de> for Block {
         D: de::Deserializer<'de>,
     {
         let helper: Value = Deserialize::deserialize(deserializer)?;
-        let tuple = (
-            decode_value(helper.get("schema")),
-            decode_value(helper.get("records")),
-        );
+        let field = |name: &str| {
+            helper
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| D::Error::custom(format!("Missing field `{}` in Block", name)))
+        };
+        let (schema_str, records_str, a_id) = (field("schema")?, field("records")?, field("aId")?);
 
-        match tuple {
-            (Some(schema), Some(records)) => {
-                // We have to make sure that this is safe
-                // TODO(magrund)
-                let fbs_schema = ipc::get_size_prefixed_root_as_message(&schema[4..]);
-                let fbs_records = ipc::get_size_prefixed_root_as_message(&records[4..]);
-
-                if fbs_schema.header_type() == MessageHeader::Schema {
-                    if let Some(fbs_schema) = fbs_schema.header_as_schema() {
-                        let ss = ipc::convert::fb_to_schema(fbs_schema);
-
-                        if fbs_records.header_type() == MessageHeader::RecordBatch {
-                            let body_length = fbs_records.bodyLength();
-
-                            if let Some(fbs_records) = fbs_records.header_as_record_batch() {
-                                // Read fom the record batch
-                                let x = rr::read_record_batch(
-                                    &records[records.len() - body_length as usize..],
-                                    fbs_records,
-                                    Arc::new(ss),
-                                );
-                                if let Ok(Some(x)) = x {
-                                    return Ok(Block::new(
-                                        x,
-                                        helper.get("schema").unwrap().as_str().unwrap().to_string(),
-                                        helper
-                                            .get("records")
-                                            .unwrap()
-                                            .as_str()
-                                            .unwrap()
-                                            .to_string(),
-                                        helper.get("aId").unwrap().as_str().unwrap().to_string(),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                }
-                return Err(D::Error::custom("Missing field `schema` in Block"));
-            }
-            (_, _) => return Err(D::Error::custom("Missing field `schema` in Block")),
-        }
+        let schema = decode_value(helper.get("schema"))
+            .ok_or_else(|| D::Error::custom("Invalid base64 field `schema` in Block"))?;
+        let records = decode_value(helper.get("records"))
+            .ok_or_else(|| D::Error::custom("Invalid base64 field `records` in Block"))?;
+
+        let ss = decode_schema(&schema).map_err(D::Error::custom)?;
+        let x = decode_record_batch(&records, Arc::new(ss)).map_err(D::Error::custom)?;
+        Ok(Block::new(
+            x,
+            schema_str.to_string(),
+            records_str.to_string(),
+            a_id.to_string(),
+        ))
     }
 }
 
 impl Serialize for Block {
-    /// Custom implementation to deserialize a Block from a given JSON record. First, we
-    /// extract the JSON string values from the known keys, then we convert them to binary
-    /// by base64 decoding them. Finally, we extract the Schema and RecordBatch messages and
-    /// deserialize them into the Arrow Array types.
+    /// Custom implementation to serialize the cached base64 encoded messages of the
+    /// Block. The fields are written in the order of the SDK.
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let mut s = serializer.serialize_struct("Block", 3)?;
+        s.serialize_field("aId", &self.serialized.a_id)?;
         s.serialize_field("schema", &self.serialized.schema)?;
         s.serialize_field("records", &self.serialized.records)?;
-        s.serialize_field("aId", &self.serialized.a_id)?;
         s.end()
     }
 }
@@ -260,7 +454,7 @@ impl Serialize for Block {
 /// The knowledge about the `SpillLocation` is used for the caller to
 /// fetch values from S3 instead of fetching them as an inline result
 /// from the invocation of the lambda function.
-#[derive(Debug, Serialize, Default, Deserialize)]
+#[derive(Debug, Serialize, Default, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SpillLocation {
     bucket: String,
@@ -274,16 +468,131 @@ impl SpillLocation {
     fn class_type_def() -> String {
         "S3SpillLocation".to_string()
     }
+
+    /// Creates a new S3 spill location. If `directory` is true, the key is
+    /// treated as the prefix of all spilled objects.
+    pub fn new(bucket: String, key: String, directory: bool) -> Self {
+        SpillLocation {
+            bucket,
+            key,
+            directory,
+            class_type: Self::class_type_def(),
+        }
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.directory
+    }
+}
+
+/// Helper module to (de)serialize binary values as base64 strings the same way
+/// Jackson serializes `byte[]` fields in the SDK.
+mod base64_bytes {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(value))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(&s).map_err(D::Error::custom)
+    }
 }
 
 /// Value struct containing information about the encryption key used
-/// by the lambda function to encrypt the results in S3.
-#[derive(Debug, Serialize, Deserialize, Default)]
+/// by the lambda function to encrypt the results in S3. The SDK uses
+/// AES-GCM with a per query key and nonce.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
-pub struct EncryptionKey {}
+pub struct EncryptionKey {
+    #[serde(with = "base64_bytes")]
+    key: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    nonce: Vec<u8>,
+}
+
+impl EncryptionKey {
+    /// Size of the generated AES keys in bytes.
+    const KEY_BYTES: usize = 32;
+    /// Size of the generated nonces in bytes.
+    const NONCE_BYTES: usize = 12;
+
+    pub fn new(key: Vec<u8>, nonce: Vec<u8>) -> Self {
+        EncryptionKey { key, nonce }
+    }
+
+    /// Generates a new random 256 bit AES key and nonce. A new key should be
+    /// generated for every query.
+    pub fn generate() -> error::Result<Self> {
+        let rng = SystemRandom::new();
+        let mut key = vec![0; Self::KEY_BYTES];
+        let mut nonce = vec![0; Self::NONCE_BYTES];
+        rng.fill(&mut key)
+            .and_then(|_| rng.fill(&mut nonce))
+            .map_err(|_| Error::Encryption("Could not generate encryption key".to_string()))?;
+        Ok(EncryptionKey { key, nonce })
+    }
+
+    /// Creates the AES-GCM key matching the length of the raw key.
+    fn aead_key(&self) -> error::Result<(aead::LessSafeKey, aead::Nonce)> {
+        let algorithm = match self.key.len() {
+            16 => &aead::AES_128_GCM,
+            32 => &aead::AES_256_GCM,
+            len => {
+                return Err(Error::Encryption(format!(
+                    "Unsupported key length of {} bytes",
+                    len
+                )))
+            }
+        };
+        let key = aead::UnboundKey::new(algorithm, &self.key)
+            .map_err(|_| Error::Encryption("Invalid encryption key".to_string()))?;
+        let nonce = aead::Nonce::try_assume_unique_for_key(&self.nonce)
+            .map_err(|_| Error::Encryption("Invalid nonce".to_string()))?;
+        Ok((aead::LessSafeKey::new(key), nonce))
+    }
+
+    /// Encrypts the data with the key. The authentication tag is appended to the
+    /// cipher text, the same way the Java `Cipher` for AES/GCM/NoPadding does.
+    pub fn encrypt(&self, data: &[u8]) -> error::Result<Vec<u8>> {
+        let (key, nonce) = self.aead_key()?;
+        let mut in_out = data.to_vec();
+        key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
+            .map_err(|_| Error::Encryption("Could not encrypt block".to_string()))?;
+        Ok(in_out)
+    }
+
+    /// Decrypts and authenticates data encrypted with the key.
+    pub fn decrypt(&self, data: &[u8]) -> error::Result<Vec<u8>> {
+        let (key, nonce) = self.aead_key()?;
+        let mut in_out = data.to_vec();
+        let plain_len = key
+            .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+            .map_err(|_| Error::Encryption("Could not decrypt block".to_string()))?
+            .len();
+        in_out.truncate(plain_len);
+        Ok(in_out)
+    }
+}
 
 /// A `Split` is a work unit used in the distribution of requests.
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Split {
     spill_location: SpillLocation,
@@ -296,27 +605,307 @@ impl Split {
     /// will simply initialize the spill location for the Split. The actual split
     /// is defined via the properties of the Split.
     fn create(bucket: String, key: String) -> Split {
-        let spill_loc = SpillLocation {
-            bucket: bucket,
-            key: key,
-            directory: true,
-            class_type: SpillLocation::class_type_def(),
-        };
+        let spill_loc = SpillLocation::new(bucket, key, true);
         Split {
             spill_location: spill_loc,
             properties: HashMap::new(),
             encryption_key: None,
         }
     }
+
+    pub fn spill_location(&self) -> &SpillLocation {
+        &self.spill_location
+    }
+
+    pub fn properties(&self) -> &HashMap<String, String> {
+        &self.properties
+    }
+
+    pub fn encryption_key(&self) -> Option<&EncryptionKey> {
+        self.encryption_key.as_ref()
+    }
+
+    /// Sets the key the lambda function uses to encrypt the spilled blocks of
+    /// this split.
+    pub fn set_encryption_key(&mut self, key: EncryptionKey) {
+        self.encryption_key = Some(key);
+    }
+}
+
+/// Position of a `Marker` relative to its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Bound {
+    /// The marker is just above its value.
+    Above,
+    /// The marker is exactly at its value.
+    Exactly,
+    /// The marker is just below its value.
+    Below,
+}
+
+/// A point on the domain of a column. The value is stored in the first row
+/// of the value block. Unbounded markers have no value and are flagged as
+/// `null_value`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Marker {
+    value_block: Block,
+    bound: Bound,
+    null_value: bool,
+}
+
+impl Marker {
+    pub fn new(value_block: Block, bound: Bound, null_value: bool) -> Self {
+        Marker {
+            value_block,
+            bound,
+            null_value,
+        }
+    }
+
+    pub fn value_block(&self) -> &Block {
+        &self.value_block
+    }
+
+    pub fn bound(&self) -> Bound {
+        self.bound
+    }
+
+    pub fn is_null_value(&self) -> bool {
+        self.null_value
+    }
+
+    pub fn is_lower_unbounded(&self) -> bool {
+        self.null_value && self.bound == Bound::Above
+    }
+
+    pub fn is_upper_unbounded(&self) -> bool {
+        self.null_value && self.bound == Bound::Below
+    }
+}
+
+/// A range of values between two markers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Range {
+    low: Marker,
+    high: Marker,
+}
+
+impl Range {
+    pub fn new(low: Marker, high: Marker) -> Self {
+        Range { low, high }
+    }
+
+    pub fn low(&self) -> &Marker {
+        &self.low
+    }
+
+    pub fn high(&self) -> &Marker {
+        &self.high
+    }
 }
 
-/// Constraints are a complicated piece of technology that was
-/// inherited by Presto. and we don't have a good way yet to
-/// deal with it.
-#[derive(Debug, Deserialize, Serialize)]
+/// Value set of all values that are within one of the sorted and
+/// non-overlapping ranges.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortedRangeSet {
+    #[serde(rename = "type", with = "arrow_type")]
+    data_type: DataType,
+    ranges: Vec<Range>,
+    null_allowed: bool,
+}
+
+impl SortedRangeSet {
+    pub fn new(data_type: DataType, ranges: Vec<Range>, null_allowed: bool) -> Self {
+        SortedRangeSet {
+            data_type,
+            ranges,
+            null_allowed,
+        }
+    }
+
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+}
+
+/// Value set of the values in the value block if it is a white list, or of all
+/// values except the values in the value block otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EquatableValueSet {
+    value_block: Block,
+    white_list: bool,
+    null_allowed: bool,
+}
+
+impl EquatableValueSet {
+    pub fn new(value_block: Block, white_list: bool, null_allowed: bool) -> Self {
+        EquatableValueSet {
+            value_block,
+            white_list,
+            null_allowed,
+        }
+    }
+
+    pub fn value_block(&self) -> &Block {
+        &self.value_block
+    }
+
+    pub fn is_white_list(&self) -> bool {
+        self.white_list
+    }
+}
+
+/// Value set of either all or none of the values of a type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AllOrNoneValueSet {
+    #[serde(rename = "type", with = "arrow_type")]
+    data_type: DataType,
+    all: bool,
+    null_allowed: bool,
+}
+
+impl AllOrNoneValueSet {
+    pub fn new(data_type: DataType, all: bool, null_allowed: bool) -> Self {
+        AllOrNoneValueSet {
+            data_type,
+            all,
+            null_allowed,
+        }
+    }
+
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+}
+
+/// The set of values of a column, tagged by the name of the Java class.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "@type")]
+pub enum ValueSet {
+    SortedRangeSet(SortedRangeSet),
+    EquatableValueSet(EquatableValueSet),
+    AllOrNoneValueSet(AllOrNoneValueSet),
+}
+
+impl ValueSet {
+    /// Returns true if the value set contains NULL.
+    pub fn is_null_allowed(&self) -> bool {
+        match self {
+            ValueSet::SortedRangeSet(s) => s.null_allowed,
+            ValueSet::EquatableValueSet(s) => s.null_allowed,
+            ValueSet::AllOrNoneValueSet(s) => s.null_allowed,
+        }
+    }
+}
+
+/// Constraints describe the values of the columns a connector has to return.
+/// The summary maps the column names to the set of values that satisfy the
+/// predicates of the query. Columns without an entry are not constrained.
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Constraints {
-    summary: HashMap<String, String>,
+    summary: HashMap<String, ValueSet>,
+    /// Expressions the rows must satisfy in addition to the summary. Only sent
+    /// to connectors supporting `SerDeVersion::V3`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    expression: Vec<FederationExpression>,
+    /// Sort order of the rows, if the query has an ORDER BY clause. Only sent
+    /// to connectors supporting `SerDeVersion::V4`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    order_by_clause: Vec<OrderByField>,
+    /// Maximum number of rows the query needs. Only sent to connectors
+    /// supporting `SerDeVersion::V4`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    limit: Option<i64>,
+    /// Arguments of the query passthrough function, if the query is a native
+    /// query of the connector. Only sent to connectors supporting
+    /// `SerDeVersion::V5`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    query_passthrough_arguments: HashMap<String, String>,
+}
+
+impl Constraints {
+    /// Name of the query passthrough argument holding the qualified name of
+    /// the passthrough function, e.g. `system.query`.
+    pub const SCHEMA_FUNCTION_NAME: &'static str = "schemaFunctionName";
+
+    pub fn new(summary: HashMap<String, ValueSet>) -> Self {
+        Constraints {
+            summary,
+            ..Default::default()
+        }
+    }
+
+    pub fn summary(&self) -> &HashMap<String, ValueSet> {
+        &self.summary
+    }
+
+    pub fn expression(&self) -> &[FederationExpression] {
+        &self.expression
+    }
+
+    pub fn order_by(&self) -> &[OrderByField] {
+        &self.order_by_clause
+    }
+
+    pub fn limit(&self) -> Option<i64> {
+        self.limit
+    }
+
+    pub fn query_passthrough_arguments(&self) -> &HashMap<String, String> {
+        &self.query_passthrough_arguments
+    }
+
+    /// Returns true if the constraints describe a native query of the
+    /// connector instead of constraints on a table.
+    pub fn is_query_passthrough(&self) -> bool {
+        !self.query_passthrough_arguments.is_empty()
+    }
+
+    /// Sets the expressions the rows must satisfy in addition to the summary.
+    pub fn with_expression(mut self, expression: Vec<FederationExpression>) -> Self {
+        self.expression = expression;
+        self
+    }
+
+    /// Sets the sort order of the rows, so that connectors can stop early
+    /// together with a limit.
+    pub fn with_order_by(mut self, order_by: Vec<OrderByField>) -> Self {
+        self.order_by_clause = order_by;
+        self
+    }
+
+    /// Sets the maximum number of rows the connector has to return.
+    pub fn with_limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Sets the arguments of the query passthrough function, including the
+    /// `SCHEMA_FUNCTION_NAME`.
+    pub fn with_query_passthrough_arguments(mut self, arguments: HashMap<String, String>) -> Self {
+        self.query_passthrough_arguments = arguments;
+        self
+    }
+
+    /// Returns a builder that translates predicates on the columns of the
+    /// schema into constraints.
+    pub fn builder(schema: &arrow::datatypes::Schema) -> ConstraintsBuilder {
+        ConstraintsBuilder::new(schema.clone())
+    }
 }
 
 impl Default for Constraints {
@@ -324,14 +913,391 @@ impl Default for Constraints {
     fn default() -> Self {
         Constraints {
             summary: HashMap::new(),
+            expression: vec![],
+            order_by_clause: vec![],
+            limit: None,
+            query_passthrough_arguments: HashMap::new(),
+        }
+    }
+}
+
+/// Variant of an optimization supported by a connector, e.g. the name of a
+/// query passthrough function together with the names of its arguments.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimizationSubType {
+    pub sub_type: String,
+    #[serde(default)]
+    pub properties: Vec<String>,
+}
+
+/// Typed view on the optimizations a connector reports in its
+/// `GetDataSourceCapabilitiesResponse`. The query engine uses it to decide
+/// which parts of a query are pushed down to the connector and which are
+/// evaluated on the returned records. Optimizations that are not reported are
+/// not supported, which includes all optimizations of connectors before SerDe
+/// version 4.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    optimizations: HashMap<String, Vec<OptimizationSubType>>,
+}
+
+impl Capabilities {
+    pub const FILTER_PUSHDOWN: &'static str = "supports_filter_pushdown";
+    pub const COMPLEX_EXPRESSION_PUSHDOWN: &'static str = "supports_complex_expression_pushdown";
+    pub const LIMIT_PUSHDOWN: &'static str = "supports_limit_pushdown";
+    pub const TOP_N_PUSHDOWN: &'static str = "supports_top_n_pushdown";
+    pub const QUERY_PASSTHROUGH: &'static str = "supports_query_passthrough";
+
+    /// Filter sub type for value sets of sorted ranges.
+    pub const SORTED_RANGE_SET: &'static str = "sorted_range_set";
+    /// Filter sub type for value sets that include NULL.
+    pub const NULLABLE_COMPARISON: &'static str = "nullable_comparison";
+    /// Complex expression sub type whose properties are the supported
+    /// function names.
+    pub const SUPPORTED_FUNCTION_EXPRESSION_TYPES: &'static str =
+        "supported_function_expression_types";
+
+    pub fn new(optimizations: HashMap<String, Vec<OptimizationSubType>>) -> Self {
+        Capabilities { optimizations }
+    }
+
+    /// Returns the reported optimizations by name.
+    pub fn optimizations(&self) -> &HashMap<String, Vec<OptimizationSubType>> {
+        &self.optimizations
+    }
+
+    fn sub_types(&self, optimization: &str) -> &[OptimizationSubType] {
+        self.optimizations
+            .get(optimization)
+            .map_or(&[], |sub_types| sub_types.as_slice())
+    }
+
+    fn has_sub_type(&self, optimization: &str, sub_type: &str) -> bool {
+        self.sub_types(optimization)
+            .iter()
+            .any(|s| s.sub_type == sub_type)
+    }
+
+    pub fn supports_filter(&self) -> bool {
+        self.optimizations.contains_key(Self::FILTER_PUSHDOWN)
+    }
+
+    /// Returns true if the connector evaluates the value set of a column
+    /// summary. Sorted range sets and value sets including NULL must be
+    /// reported as sub types of the filter pushdown.
+    pub fn supports_value_set(&self, value_set: &ValueSet) -> bool {
+        if !self.supports_filter() {
+            return false;
+        }
+        let ranges = match value_set {
+            ValueSet::SortedRangeSet(_) => {
+                self.has_sub_type(Self::FILTER_PUSHDOWN, Self::SORTED_RANGE_SET)
+            }
+            _ => true,
+        };
+        let nulls = match value_set {
+            ValueSet::AllOrNoneValueSet(_) => true,
+            _ => {
+                !value_set.is_null_allowed()
+                    || self.has_sub_type(Self::FILTER_PUSHDOWN, Self::NULLABLE_COMPARISON)
+            }
+        };
+        ranges && nulls
+    }
+
+    /// Returns true if the connector evaluates calls of the function, e.g.
+    /// `FunctionName::ADD`.
+    pub fn supports_function(&self, function_name: &str) -> bool {
+        self.sub_types(Self::COMPLEX_EXPRESSION_PUSHDOWN)
+            .iter()
+            .filter(|s| s.sub_type == Self::SUPPORTED_FUNCTION_EXPRESSION_TYPES)
+            .any(|s| s.properties.iter().any(|p| p == function_name))
+    }
+
+    /// Returns true if the connector evaluates all function calls of the
+    /// expression.
+    pub fn supports_expression(&self, expression: &FederationExpression) -> bool {
+        match expression {
+            FederationExpression::FunctionCallExpression(e) => {
+                self.supports_function(e.function_name.name())
+                    && e.arguments.iter().all(|a| self.supports_expression(a))
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns true if the connector applies a limit without an ORDER BY.
+    pub fn supports_limit(&self) -> bool {
+        self.optimizations.contains_key(Self::LIMIT_PUSHDOWN)
+    }
+
+    /// Returns true if the connector applies a limit after an ORDER BY.
+    pub fn supports_top_n(&self) -> bool {
+        self.optimizations.contains_key(Self::TOP_N_PUSHDOWN)
+    }
+
+    /// Returns the query passthrough functions of the connector. The sub type
+    /// is the signature of the function, e.g. `system.query`, the properties
+    /// are the names of its arguments.
+    pub fn passthrough_functions(&self) -> &[OptimizationSubType] {
+        self.sub_types(Self::QUERY_PASSTHROUGH)
+    }
+
+    /// Returns true if the connector supports the query passthrough function
+    /// `function_schema.function_name`.
+    pub fn supports_passthrough(&self, function_schema: &str, function_name: &str) -> bool {
+        let signature = format!("{}.{}", function_schema, function_name);
+        self.passthrough_functions()
+            .iter()
+            .any(|f| f.sub_type.eq_ignore_ascii_case(&signature))
+    }
+}
+
+/// Sort direction and position of NULL values of an ORDER BY field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SortDirection {
+    AscNullsFirst,
+    AscNullsLast,
+    DescNullsFirst,
+    DescNullsLast,
+}
+
+/// A column of the ORDER BY clause of a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderByField {
+    column_name: String,
+    direction: SortDirection,
+}
+
+impl OrderByField {
+    pub fn new(column_name: String, direction: SortDirection) -> Self {
+        OrderByField {
+            column_name,
+            direction,
+        }
+    }
+
+    pub fn column_name(&self) -> &str {
+        &self.column_name
+    }
+
+    pub fn direction(&self) -> SortDirection {
+        self.direction
+    }
+}
+
+/// Name of the function of a `FunctionCallExpression`. The names of the
+/// standard functions understood by the SDK are provided as constants.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionName {
+    function_name: String,
+}
+
+impl FunctionName {
+    pub const ADD: &'static str = "$add";
+    pub const SUBTRACT: &'static str = "$subtract";
+    pub const MULTIPLY: &'static str = "$multiply";
+    pub const DIVIDE: &'static str = "$divide";
+    pub const MODULUS: &'static str = "$modulus";
+    pub const NEGATE: &'static str = "$negate";
+    pub const EQUAL: &'static str = "$equal";
+    pub const NOT_EQUAL: &'static str = "$not_equal";
+    pub const LESS_THAN: &'static str = "$less_than";
+    pub const LESS_THAN_OR_EQUAL: &'static str = "$less_than_or_equal";
+    pub const GREATER_THAN: &'static str = "$greater_than";
+    pub const GREATER_THAN_OR_EQUAL: &'static str = "$greater_than_or_equal";
+    pub const AND: &'static str = "$and";
+    pub const OR: &'static str = "$or";
+    pub const NOT: &'static str = "$not";
+    pub const IS_NULL: &'static str = "$is_null";
+    pub const LIKE_PATTERN: &'static str = "$like_pattern";
+    pub const IN: &'static str = "$in";
+
+    pub fn new(function_name: &str) -> Self {
+        FunctionName {
+            function_name: function_name.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.function_name
+    }
+
+    /// Returns true if the function is a comparison, a logical operator or a
+    /// test, i.e. if it returns a boolean.
+    pub fn is_boolean(&self) -> bool {
+        [
+            Self::EQUAL,
+            Self::NOT_EQUAL,
+            Self::LESS_THAN,
+            Self::LESS_THAN_OR_EQUAL,
+            Self::GREATER_THAN,
+            Self::GREATER_THAN_OR_EQUAL,
+            Self::AND,
+            Self::OR,
+            Self::NOT,
+            Self::IS_NULL,
+            Self::LIKE_PATTERN,
+            Self::IN,
+        ]
+        .contains(&self.function_name.as_str())
+    }
+}
+
+/// Call of a function with the given arguments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallExpression {
+    #[serde(rename = "type", with = "arrow_type")]
+    data_type: DataType,
+    function_name: FunctionName,
+    arguments: Vec<FederationExpression>,
+}
+
+impl FunctionCallExpression {
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn function_name(&self) -> &FunctionName {
+        &self.function_name
+    }
+
+    pub fn arguments(&self) -> &[FederationExpression] {
+        &self.arguments
+    }
+}
+
+/// Constant values, stored in the value block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConstantExpression {
+    #[serde(rename = "type", with = "arrow_type")]
+    data_type: DataType,
+    value_block: Block,
+}
+
+impl ConstantExpression {
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn value_block(&self) -> &Block {
+        &self.value_block
+    }
+}
+
+/// Reference to the values of a column.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariableExpression {
+    #[serde(rename = "type", with = "arrow_type")]
+    data_type: DataType,
+    column_name: String,
+}
+
+impl VariableExpression {
+    pub fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+
+    pub fn column_name(&self) -> &str {
+        &self.column_name
+    }
+}
+
+/// Expression pushed down to connectors in addition to the value sets of the
+/// constraints, tagged by the name of the Java class. Expressions can describe
+/// predicates that value sets cannot, e.g. LIKE, arithmetic or an OR across
+/// several columns. Expressions are built with `predicate::var` and
+/// `predicate::lit`, or with the constructors of this type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "@type")]
+pub enum FederationExpression {
+    FunctionCallExpression(FunctionCallExpression),
+    ConstantExpression(ConstantExpression),
+    VariableExpression(VariableExpression),
+}
+
+impl FederationExpression {
+    /// Creates a call of the function returning values of the given type.
+    pub fn call(
+        function_name: &str,
+        data_type: DataType,
+        arguments: Vec<FederationExpression>,
+    ) -> Self {
+        FederationExpression::FunctionCallExpression(FunctionCallExpression {
+            data_type,
+            function_name: FunctionName::new(function_name),
+            arguments,
+        })
+    }
+
+    /// Creates a constant with the values of the first column of the block.
+    pub fn constant(value_block: Block, data_type: DataType) -> Self {
+        FederationExpression::ConstantExpression(ConstantExpression {
+            data_type,
+            value_block,
+        })
+    }
+
+    /// Creates a reference to the column with the given type.
+    pub fn variable(column_name: &str, data_type: DataType) -> Self {
+        FederationExpression::VariableExpression(VariableExpression {
+            data_type,
+            column_name: column_name.to_string(),
+        })
+    }
+
+    /// Returns the type of the values of the expression.
+    pub fn data_type(&self) -> &DataType {
+        match self {
+            FederationExpression::FunctionCallExpression(e) => &e.data_type,
+            FederationExpression::ConstantExpression(e) => &e.data_type,
+            FederationExpression::VariableExpression(e) => &e.data_type,
         }
     }
 }
 
+/// Serializes Arrow data types in the JSON format of the Java SDK, which is
+/// the format of the Arrow integration tests, e.g.
+/// `{"name":"int","bitWidth":32,"isSigned":true}`.
+pub(crate) mod arrow_type {
+    use arrow::datatypes::{DataType, Field};
+    use serde::de::{self, Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+    use serde_json::{json, Value};
+
+    pub fn serialize<S>(data_type: &DataType, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        data_type.to_json().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DataType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Arrow only exposes the parser of the data types as part of the
+        // parser of fields.
+        let json = Value::deserialize(deserializer)?;
+        let field = json!({ "name": "", "nullable": true, "type": json, "children": [] });
+        Field::from(&field)
+            .map(|f| f.data_type().clone())
+            .map_err(|e| de::Error::custom(format!("Invalid Arrow type: {:?}", e)))
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::test_util::{block_json, SCHEMA};
 
     fn init() {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -365,26 +1331,62 @@ mod test {
         let mut schema_new = Schema::new_from_string(schema_str_new);
         assert!(schema_new.arrow_schema.is_none());
         let the_schema = schema_new.get_schema();
-        assert!(the_schema.is_some());
+        assert!(the_schema.is_ok());
 
         let mut schema_old = Schema::new_from_string(schema_str_old);
         assert!(schema_old.arrow_schema.is_none());
-        assert!(schema_old.get_schema().is_some());
+        assert!(schema_old.get_schema().is_ok());
     }
 
     #[test]
     fn test_block_deserializing() {
-        let json = r#"{
-            "schema": "/////xABAAAQAAAAAAAKAA4ABgANAAgACgAAAAAAAwAQAAAAAAEKAAwAAAAIAAQACgAAAAgAAAAIAAAAAAAAAAMAAACcAAAAPAAAAAQAAACC////FAAAABQAAAAUAAAAAAAFARAAAAAAAAAAAAAAAHD///8JAAAAbG9nX2dyb3VwAAAAtv///xQAAAAUAAAAHAAAAAAAAgEgAAAAAAAAAAAAAAAIAAwACAAHAAgAAAAAAAABQAAAABAAAABsb2dfc3RyZWFtX2J5dGVzAAASABgAFAATABIADAAAAAgABAASAAAAFAAAABQAAAAYAAAAAAAFARQAAAAAAAAAAAAAAAQABAAEAAAACgAAAGxvZ19zdHJlYW0AAA==",
-            "records": "/////wgBAAAUAAAAAAAAAAwAFgAOABUAEAAEAAwAAACAAAAAAAAAAAAAAwAQAAAAAAMKABgADAAIAAQACgAAABQAAACYAAAAAQAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAQAAAAAAAAAIAAAAAAAAAAgAAAAAAAAAEAAAAAAAAAA0AAAAAAAAAEgAAAAAAAAAAQAAAAAAAABQAAAAAAAAAAgAAAAAAAAAWAAAAAAAAAABAAAAAAAAAGAAAAAAAAAACAAAAAAAAABoAAAAAAAAABIAAAAAAAAAAAAAAAMAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAA0AAAAMjAxOS8xMS8xNi9bJExBVEVTVF0wNTM0NmI2MTExMWI0YWQ2OTZkOTRiYTYwZTQ3MzRiNgAAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAEgAAAC9hd3MvbGFtYmRhL2N3dGVzdAAAAAAAAA==", 
-            "aId": "52fb8f5f-e2d0-4345-84d4-5f651bee361b"
-            }"#;
-
-        let block: Block = serde_json::from_str(json).unwrap();
+        let block: Block = serde_json::from_value(block_json()).unwrap();
         assert_eq!(1, block.records.num_rows());
         assert_eq!(3, block.records.num_columns());
     }
 
+    #[test]
+    fn test_block_from_record_batch() {
+        let block: Block = serde_json::from_value(block_json()).unwrap();
+        let batch = block.into_record_batch();
+        let block = Block::from_record_batch(batch.clone(), "a_id".to_string()).unwrap();
+        assert_eq!("a_id", block.allocator_id());
+
+        let json = serde_json::to_string(&block).unwrap();
+        assert!(json.starts_with(r#"{"aId":"a_id","schema":"/////"#));
+        let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let records = base64::decode(val["records"].as_str().unwrap()).unwrap();
+        assert!(records.starts_with(&CONTINUATION_MARKER));
+
+        let decoded: Block = serde_json::from_str(&json).unwrap();
+        assert_eq!(batch.schema(), decoded.record_batch().schema());
+        assert_eq!(batch.num_rows(), decoded.record_batch().num_rows());
+        let strings = |b: &RecordBatch| {
+            b.column(2)
+                .as_any()
+                .downcast_ref::<arrow::array::StringArray>()
+                .unwrap()
+                .value(0)
+                .to_string()
+        };
+        assert_eq!(strings(&batch), strings(decoded.record_batch()));
+
+        let legacy = ipc_for_version(&block.serialized.records, SerDeVersion::V1).unwrap();
+        assert!(!base64::decode(&legacy)
+            .unwrap()
+            .starts_with(&CONTINUATION_MARKER));
+        assert_ne!(random_allocator_id(), random_allocator_id());
+    }
+
+    #[test]
+    fn test_block_deserializing_errors() {
+        let missing_a_id = r#"{ "schema": "", "records": "" }"#;
+        assert!(serde_json::from_str::<Block>(missing_a_id).is_err());
+
+        let truncated = r#"{ "schema": "/////w==", "records": "/////w==", "aId": "a" }"#;
+        assert!(serde_json::from_str::<Block>(truncated).is_err());
+    }
+
     #[test]
     fn test_spill_location() {
         let json = r#"
@@ -403,6 +1405,38 @@ mod test {
         assert_eq!(sl_val, val);
     }
 
+    #[test]
+    fn test_encryption_key() {
+        let key = EncryptionKey::generate().unwrap();
+        assert_eq!(32, key.key.len());
+        assert_eq!(12, key.nonce.len());
+
+        let data = b"some spilled block".to_vec();
+        let encrypted = key.encrypt(&data).unwrap();
+        assert_ne!(data, encrypted);
+        assert_eq!(data, key.decrypt(&encrypted).unwrap());
+
+        let other = EncryptionKey::generate().unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn test_encryption_key_serde() {
+        let json = r#"{
+            "key": "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+            "nonce": "AAECAwQFBgcICQoL"
+        }"#;
+
+        let key: EncryptionKey = serde_json::from_str(json).unwrap();
+        assert_eq!((0..32).collect::<Vec<u8>>(), key.key);
+        assert_eq!((0..12).collect::<Vec<u8>>(), key.nonce);
+        let val: serde_json::Value = serde_json::to_value(&key).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(json).unwrap(),
+            val
+        );
+    }
+
     #[test]
     fn test_split_serde() {
         let json = r#"{
@@ -426,4 +1460,191 @@ mod test {
         let val: serde_json::Value = serde_json::to_value(sl).unwrap();
         assert_eq!(sl_val, val);
     }
+
+    #[test]
+    fn test_ipc_for_version() {
+        let schema = SCHEMA;
+        assert_eq!(schema, ipc_for_version(schema, SerDeVersion::V2).unwrap());
+
+        let legacy = ipc_for_version(schema, SerDeVersion::V1).unwrap();
+        let decoded = base64::decode(&legacy).unwrap();
+        assert!(!decoded.starts_with(&CONTINUATION_MARKER));
+        assert_eq!(3, decode_schema(&decoded).unwrap().fields().len());
+        assert_eq!(schema, ipc_for_version(&legacy, SerDeVersion::V2).unwrap());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let optimizations = serde_json::from_value(serde_json::json!({
+            "supports_filter_pushdown": [{ "subType": "sorted_range_set" }],
+            "supports_complex_expression_pushdown": [{
+                "subType": "supported_function_expression_types",
+                "properties": ["$add", "$equal"]
+            }],
+            "supports_top_n_pushdown": [{ "subType": "supports_order_by" }],
+            "supports_query_passthrough": [
+                { "subType": "system.query", "properties": ["QUERY"] }
+            ]
+        }))
+        .unwrap();
+        let c = Capabilities::new(optimizations);
+        assert!(c.supports_filter());
+        assert!(c.supports_top_n());
+        assert!(!c.supports_limit());
+        assert!(c.supports_function(FunctionName::ADD));
+        assert!(!c.supports_function(FunctionName::LIKE_PATTERN));
+        assert!(c.supports_passthrough("SYSTEM", "query"));
+        assert!(!c.supports_passthrough("system", "table"));
+
+        let int = DataType::Int64;
+        let a = FederationExpression::variable("a", int.clone());
+        let b = FederationExpression::variable("b", int.clone());
+        let add = FederationExpression::call(FunctionName::ADD, int.clone(), vec![a.clone(), b]);
+        let eq = FederationExpression::call(FunctionName::EQUAL, DataType::Boolean, vec![add, a]);
+        assert!(c.supports_expression(&eq));
+        let like =
+            FederationExpression::call(FunctionName::LIKE_PATTERN, DataType::Boolean, vec![eq]);
+        assert!(!c.supports_expression(&like));
+
+        let s = arrow::datatypes::Schema::new(vec![arrow::datatypes::Field::new("a", int, true)]);
+        let ranges = Constraints::builder(&s)
+            .filter(crate::predicate::col("a").gt(1))
+            .build()
+            .unwrap();
+        assert!(c.supports_value_set(&ranges.summary()["a"]));
+        let nulls = Constraints::builder(&s)
+            .filter(
+                crate::predicate::col("a")
+                    .gt(1)
+                    .or(crate::predicate::col("a").is_null()),
+            )
+            .build()
+            .unwrap();
+        assert!(!c.supports_value_set(&nulls.summary()["a"]));
+        assert!(!Capabilities::default().supports_value_set(&ranges.summary()["a"]));
+    }
+
+    #[test]
+    fn test_federation_expression_serde() {
+        let block: Value = serde_json::from_value(block_json()).unwrap();
+        let json = serde_json::json!({
+            "@type": "FunctionCallExpression",
+            "type": { "name": "bool" },
+            "functionName": { "functionName": "$like_pattern" },
+            "arguments": [
+                {
+                    "@type": "VariableExpression",
+                    "type": { "name": "utf8" },
+                    "columnName": "log_stream"
+                },
+                {
+                    "@type": "ConstantExpression",
+                    "type": { "name": "utf8" },
+                    "valueBlock": block
+                }
+            ]
+        });
+
+        let expression: FederationExpression = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(&DataType::Boolean, expression.data_type());
+        match &expression {
+            FederationExpression::FunctionCallExpression(e) => {
+                assert!(e.function_name().is_boolean());
+                match &e.arguments()[0] {
+                    FederationExpression::VariableExpression(v) => {
+                        assert_eq!("log_stream", v.column_name())
+                    }
+                    a => panic!("Unexpected argument {:?}", a),
+                }
+            }
+            e => panic!("Unexpected expression {:?}", e),
+        }
+        assert_eq!(json, serde_json::to_value(&expression).unwrap());
+    }
+
+    #[test]
+    fn test_serde_version() {
+        assert_eq!(SerDeVersion::V1, SerDeVersion::negotiate(SerDeVersion::V1));
+        assert_eq!(
+            SerDeVersion::LATEST,
+            SerDeVersion::negotiate(SerDeVersion::new(100))
+        );
+        let v: SerDeVersion = serde_json::from_str("2").unwrap();
+        assert_eq!(SerDeVersion::V2, v);
+    }
+
+    fn round_trip(json: Value) -> ValueSet {
+        let value_set: ValueSet = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(json, serde_json::to_value(&value_set).unwrap());
+        value_set
+    }
+
+    #[test]
+    fn test_sorted_range_set() {
+        let json = serde_json::json!({
+            "@type": "SortedRangeSet",
+            "type": { "name": "int", "bitWidth": 32, "isSigned": true },
+            "ranges": [{
+                "low": { "valueBlock": block_json(), "bound": "EXACTLY", "nullValue": false },
+                "high": { "valueBlock": block_json(), "bound": "BELOW", "nullValue": true }
+            }],
+            "nullAllowed": false
+        });
+
+        match round_trip(json) {
+            ValueSet::SortedRangeSet(s) => {
+                assert_eq!(&DataType::Int32, s.data_type());
+                assert_eq!(1, s.ranges().len());
+                let range = &s.ranges()[0];
+                assert_eq!(Bound::Exactly, range.low().bound());
+                assert!(!range.low().is_lower_unbounded());
+                assert!(range.high().is_upper_unbounded());
+                assert_eq!(1, range.low().value_block().record_batch().num_rows());
+            }
+            v => panic!("Unexpected value set {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_equatable_value_set() {
+        let json = serde_json::json!({
+            "@type": "EquatableValueSet",
+            "valueBlock": block_json(),
+            "whiteList": true,
+            "nullAllowed": true
+        });
+
+        let value_set = round_trip(json);
+        assert!(value_set.is_null_allowed());
+        match value_set {
+            ValueSet::EquatableValueSet(s) => assert!(s.is_white_list()),
+            v => panic!("Unexpected value set {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_all_or_none_value_set() {
+        let json = serde_json::json!({
+            "@type": "AllOrNoneValueSet",
+            "type": { "name": "utf8" },
+            "all": false,
+            "nullAllowed": true
+        });
+
+        match round_trip(json) {
+            ValueSet::AllOrNoneValueSet(s) => {
+                assert_eq!(&DataType::Utf8, s.data_type());
+                assert!(!s.is_all());
+            }
+            v => panic!("Unexpected value set {:?}", v),
+        }
+
+        let invalid = serde_json::json!({
+            "@type": "AllOrNoneValueSet",
+            "type": { "name": "no_such_type" },
+            "all": true,
+            "nullAllowed": true
+        });
+        assert!(serde_json::from_value::<ValueSet>(invalid).is_err());
+    }
 }
@@ -0,0 +1,1329 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Builder for the `Constraints` of a query. Instead of building the value
+//! sets of the summary by hand, predicates can be built with `col` and
+//! translated into `Constraints` with `Constraints::builder`:
+//!
+//! ```ignore
+//! let constraints = Constraints::builder(&schema)
+//!     .filter(col("time").between(1_573_000_000_000i64, 1_574_000_000_000i64))
+//!     .filter(col("log_stream").in_list(vec!["a", "b"]).or(col("log_stream").is_null()))
+//!     .build()?;
+//! ```
+
+use super::error::{Error, Result};
+use super::models::{
+    random_allocator_id, AllOrNoneValueSet, Block, Bound, Constraints, EquatableValueSet,
+    FederationExpression, FunctionName, Marker, OrderByField, Range, SortDirection, SortedRangeSet,
+    ValueSet,
+};
+use arrow::array::*;
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Not;
+use std::sync::Arc;
+
+/// A literal value of a predicate. Literals are converted to the type of the
+/// column they are compared with when the constraints are built.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Scalar {
+    Boolean(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Utf8(String),
+}
+
+macro_rules! scalar_from {
+    ($variant:ident, $target:ty, $($t:ty),*) => {
+        $(
+            impl From<$t> for Scalar {
+                fn from(v: $t) -> Self {
+                    Scalar::$variant(<$target>::from(v))
+                }
+            }
+        )*
+    };
+}
+
+scalar_from!(Boolean, bool, bool);
+scalar_from!(Int, i64, i8, i16, i32, i64);
+scalar_from!(UInt, u64, u8, u16, u32, u64);
+scalar_from!(Float, f64, f32, f64);
+scalar_from!(Utf8, String, &str, String);
+
+impl Scalar {
+    fn to_i64(&self) -> Option<i64> {
+        match *self {
+            Scalar::Int(v) => Some(v),
+            Scalar::UInt(v) if v <= i64::max_value() as u64 => Some(v as i64),
+            _ => None,
+        }
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        match *self {
+            Scalar::Int(v) if v >= 0 => Some(v as u64),
+            Scalar::UInt(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Scalar::Int(v) => Some(v as f64),
+            Scalar::UInt(v) => Some(v as f64),
+            Scalar::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Scalar::Boolean(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Scalar::Utf8(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+/// Endpoint of an interval with its value and whether the value is included.
+/// `None` is an unbounded endpoint.
+type Endpoint = Option<(Scalar, bool)>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Interval {
+    low: Endpoint,
+    high: Endpoint,
+}
+
+impl Interval {
+    fn all() -> Self {
+        Interval {
+            low: None,
+            high: None,
+        }
+    }
+
+    fn point(value: Scalar) -> Self {
+        Interval {
+            low: Some((value.clone(), true)),
+            high: Some((value, true)),
+        }
+    }
+
+    fn is_all(&self) -> bool {
+        self.low.is_none() && self.high.is_none()
+    }
+
+    fn is_empty(&self) -> bool {
+        match (&self.low, &self.high) {
+            (Some((low, low_inclusive)), Some((high, high_inclusive))) => {
+                match compare(low, high) {
+                    Ordering::Less => false,
+                    Ordering::Equal => !(*low_inclusive && *high_inclusive),
+                    Ordering::Greater => true,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Orders two converted values. NaN is rejected by the conversion, so all
+/// values of the same type are ordered.
+fn compare(a: &Scalar, b: &Scalar) -> Ordering {
+    a.partial_cmp(b).unwrap_or(Ordering::Equal)
+}
+
+/// Orders lower endpoints, the unbounded endpoint first.
+fn compare_low(a: &Endpoint, b: &Endpoint) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, _) => Ordering::Less,
+        (_, None) => Ordering::Greater,
+        (Some((a, a_inclusive)), Some((b, b_inclusive))) => {
+            compare(a, b).then(b_inclusive.cmp(a_inclusive))
+        }
+    }
+}
+
+/// Orders upper endpoints, the unbounded endpoint last.
+fn compare_high(a: &Endpoint, b: &Endpoint) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, _) => Ordering::Greater,
+        (_, None) => Ordering::Less,
+        (Some((a, a_inclusive)), Some((b, b_inclusive))) => {
+            compare(a, b).then(a_inclusive.cmp(b_inclusive))
+        }
+    }
+}
+
+/// Returns true if an interval ending at `high` overlaps or touches an
+/// interval starting at `low`.
+fn connected(high: &Endpoint, low: &Endpoint) -> bool {
+    match (high, low) {
+        (Some((high, high_inclusive)), Some((low, low_inclusive))) => match compare(high, low) {
+            Ordering::Less => false,
+            Ordering::Equal => *high_inclusive || *low_inclusive,
+            Ordering::Greater => true,
+        },
+        _ => true,
+    }
+}
+
+/// Sorts the intervals and merges overlapping and touching intervals.
+fn normalize(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.retain(|i| !i.is_empty());
+    intervals.sort_by(|a, b| compare_low(&a.low, &b.low));
+    let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+    for interval in intervals {
+        if let Some(last) = merged.last_mut() {
+            if connected(&last.high, &interval.low) {
+                if compare_high(&interval.high, &last.high) == Ordering::Greater {
+                    last.high = interval.high;
+                }
+                continue;
+            }
+        }
+        merged.push(interval);
+    }
+    merged
+}
+
+/// Returns the gaps between the normalized intervals.
+fn complement(intervals: &[Interval]) -> Vec<Interval> {
+    let mut gaps = Vec::new();
+    // Lower endpoint of the next gap, `None` once an interval is unbounded.
+    let mut low: Option<Endpoint> = Some(None);
+    for interval in intervals {
+        if let (Some(gap_low), Some((value, inclusive))) = (&low, &interval.low) {
+            let gap = Interval {
+                low: gap_low.clone(),
+                high: Some((value.clone(), !*inclusive)),
+            };
+            if !gap.is_empty() {
+                gaps.push(gap);
+            }
+        }
+        low = interval
+            .high
+            .as_ref()
+            .map(|(value, inclusive)| Some((value.clone(), !*inclusive)));
+    }
+    if let Some(gap_low) = low {
+        gaps.push(Interval {
+            low: gap_low,
+            high: None,
+        });
+    }
+    gaps
+}
+
+fn union_values(a: &[Scalar], b: &[Scalar]) -> Vec<Scalar> {
+    let mut values = a.to_vec();
+    for v in b {
+        if !values.contains(v) {
+            values.push(v.clone());
+        }
+    }
+    values
+}
+
+fn intersect_values(a: &[Scalar], b: &[Scalar]) -> Vec<Scalar> {
+    a.iter().filter(|v| b.contains(v)).cloned().collect()
+}
+
+fn subtract_values(a: &[Scalar], b: &[Scalar]) -> Vec<Scalar> {
+    a.iter().filter(|v| !b.contains(v)).cloned().collect()
+}
+
+/// The non-null values of a column that satisfy a predicate.
+#[derive(Debug, Clone)]
+enum Values {
+    /// Sorted and non-overlapping intervals of an orderable type.
+    Ranges(Vec<Interval>),
+    /// Values of a type that can only be compared for equality. A white list
+    /// contains the listed values, a black list all other values.
+    Discrete {
+        values: Vec<Scalar>,
+        white_list: bool,
+    },
+}
+
+impl Values {
+    fn all(orderable: bool) -> Self {
+        if orderable {
+            Values::Ranges(vec![Interval::all()])
+        } else {
+            Values::Discrete {
+                values: vec![],
+                white_list: false,
+            }
+        }
+    }
+
+    fn none(orderable: bool) -> Self {
+        if orderable {
+            Values::Ranges(vec![])
+        } else {
+            Values::Discrete {
+                values: vec![],
+                white_list: true,
+            }
+        }
+    }
+
+    fn of(orderable: bool, values: Vec<Scalar>) -> Self {
+        if orderable {
+            Values::Ranges(normalize(values.into_iter().map(Interval::point).collect()))
+        } else {
+            Values::Discrete {
+                values: union_values(&[], &values),
+                white_list: true,
+            }
+        }
+    }
+
+    fn is_all(&self) -> bool {
+        match self {
+            Values::Ranges(r) => r.len() == 1 && r[0].is_all(),
+            Values::Discrete { values, white_list } => !*white_list && values.is_empty(),
+        }
+    }
+
+    fn is_none(&self) -> bool {
+        match self {
+            Values::Ranges(r) => r.is_empty(),
+            Values::Discrete { values, white_list } => *white_list && values.is_empty(),
+        }
+    }
+
+    fn complement(&self) -> Values {
+        match self {
+            Values::Ranges(r) => Values::Ranges(complement(r)),
+            Values::Discrete { values, white_list } => Values::Discrete {
+                values: values.clone(),
+                white_list: !*white_list,
+            },
+        }
+    }
+
+    fn union(&self, other: &Values) -> Values {
+        match (self, other) {
+            (Values::Ranges(a), Values::Ranges(b)) => {
+                Values::Ranges(normalize(a.iter().chain(b).cloned().collect()))
+            }
+            (
+                Values::Discrete {
+                    values: a,
+                    white_list: true,
+                },
+                Values::Discrete {
+                    values: b,
+                    white_list: true,
+                },
+            ) => Values::Discrete {
+                values: union_values(a, b),
+                white_list: true,
+            },
+            (
+                Values::Discrete {
+                    values: a,
+                    white_list: false,
+                },
+                Values::Discrete {
+                    values: b,
+                    white_list: false,
+                },
+            ) => Values::Discrete {
+                values: intersect_values(a, b),
+                white_list: false,
+            },
+            (
+                Values::Discrete {
+                    values: white,
+                    white_list: true,
+                },
+                Values::Discrete { values: black, .. },
+            )
+            | (
+                Values::Discrete { values: black, .. },
+                Values::Discrete {
+                    values: white,
+                    white_list: true,
+                },
+            ) => Values::Discrete {
+                values: subtract_values(black, white),
+                white_list: false,
+            },
+            _ => unreachable!("The values of a column are either ranges or discrete"),
+        }
+    }
+
+    fn intersect(&self, other: &Values) -> Values {
+        self.complement().union(&other.complement()).complement()
+    }
+}
+
+/// The values of a column that satisfy a predicate, including whether NULL
+/// satisfies it.
+#[derive(Debug, Clone)]
+struct Domain {
+    values: Values,
+    null_allowed: bool,
+}
+
+impl Domain {
+    fn union(&self, other: &Domain) -> Domain {
+        Domain {
+            values: self.values.union(&other.values),
+            null_allowed: self.null_allowed || other.null_allowed,
+        }
+    }
+
+    fn intersect(&self, other: &Domain) -> Domain {
+        Domain {
+            values: self.values.intersect(&other.values),
+            null_allowed: self.null_allowed && other.null_allowed,
+        }
+    }
+
+    /// Negates the domain. Like in SQL, a negated comparison is not satisfied
+    /// by NULL, so NULL is only allowed for the negation of a pure null test.
+    fn complement(&self) -> Domain {
+        let null_test = self.values.is_all() || self.values.is_none();
+        Domain {
+            values: self.values.complement(),
+            null_allowed: null_test && !self.null_allowed,
+        }
+    }
+}
+
+/// Test of the values of a single column.
+#[derive(Debug, Clone)]
+enum Test {
+    Range(Interval),
+    InList(Vec<Scalar>),
+    IsNull,
+    IsNotNull,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Column(String, Test),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// A predicate on the columns of a table. Predicates are built from the
+/// columns returned by `col` and combined with `and`, `or` and `not`.
+#[derive(Debug, Clone)]
+pub struct Predicate(Expr);
+
+impl Predicate {
+    /// Returns a predicate that is satisfied if both predicates are satisfied.
+    pub fn and(self, other: Predicate) -> Predicate {
+        Predicate(Expr::And(Box::new(self.0), Box::new(other.0)))
+    }
+
+    /// Returns a predicate that is satisfied if either predicate is satisfied.
+    /// Both predicates must refer to the same column.
+    pub fn or(self, other: Predicate) -> Predicate {
+        Predicate(Expr::Or(Box::new(self.0), Box::new(other.0)))
+    }
+}
+
+impl Not for Predicate {
+    type Output = Predicate;
+
+    /// Negates the predicate. The predicate must refer to a single column.
+    fn not(self) -> Predicate {
+        Predicate(Expr::Not(Box::new(self.0)))
+    }
+}
+
+/// Negates the predicate. The predicate must refer to a single column.
+pub fn not(predicate: Predicate) -> Predicate {
+    !predicate
+}
+
+/// A column of a table, used to build predicates.
+#[derive(Debug, Clone)]
+pub struct Column(String);
+
+/// Returns the column with the given name.
+pub fn col(name: &str) -> Column {
+    Column(name.to_string())
+}
+
+impl Column {
+    fn test(self, test: Test) -> Predicate {
+        Predicate(Expr::Column(self.0, test))
+    }
+
+    fn range(self, low: Endpoint, high: Endpoint) -> Predicate {
+        self.test(Test::Range(Interval { low, high }))
+    }
+
+    pub fn eq<V: Into<Scalar>>(self, value: V) -> Predicate {
+        self.test(Test::InList(vec![value.into()]))
+    }
+
+    pub fn not_eq<V: Into<Scalar>>(self, value: V) -> Predicate {
+        !self.eq(value)
+    }
+
+    pub fn lt<V: Into<Scalar>>(self, value: V) -> Predicate {
+        self.range(None, Some((value.into(), false)))
+    }
+
+    pub fn lt_eq<V: Into<Scalar>>(self, value: V) -> Predicate {
+        self.range(None, Some((value.into(), true)))
+    }
+
+    pub fn gt<V: Into<Scalar>>(self, value: V) -> Predicate {
+        self.range(Some((value.into(), false)), None)
+    }
+
+    pub fn gt_eq<V: Into<Scalar>>(self, value: V) -> Predicate {
+        self.range(Some((value.into(), true)), None)
+    }
+
+    /// Returns a predicate that is satisfied by the values between `low` and
+    /// `high`, both inclusive.
+    pub fn between<V: Into<Scalar>>(self, low: V, high: V) -> Predicate {
+        self.range(Some((low.into(), true)), Some((high.into(), true)))
+    }
+
+    pub fn in_list<I, V>(self, values: I) -> Predicate
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Scalar>,
+    {
+        self.test(Test::InList(values.into_iter().map(Into::into).collect()))
+    }
+
+    pub fn is_null(self) -> Predicate {
+        self.test(Test::IsNull)
+    }
+
+    pub fn is_not_null(self) -> Predicate {
+        self.test(Test::IsNotNull)
+    }
+}
+
+/// An expression on the columns of a table that is pushed down as a
+/// `FederationExpression`. Unlike predicates, expressions can use functions
+/// and refer to several columns. Expressions are built from `var` and `lit`.
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Variable(String),
+    Literal(Scalar),
+    Call(String, Vec<Expression>),
+}
+
+/// Returns an expression referring to the column with the given name.
+pub fn var(name: &str) -> Expression {
+    Expression::Variable(name.to_string())
+}
+
+/// Returns a constant expression. The constant has the type of the other
+/// arguments of the function it is passed to.
+pub fn lit<V: Into<Scalar>>(value: V) -> Expression {
+    Expression::Literal(value.into())
+}
+
+impl Expression {
+    /// Returns a call of the function with the given arguments, e.g. one of
+    /// the functions of `FunctionName`.
+    pub fn call(function_name: &str, arguments: Vec<Expression>) -> Expression {
+        Expression::Call(function_name.to_string(), arguments)
+    }
+
+    fn binary(self, function_name: &str, other: Expression) -> Expression {
+        Expression::call(function_name, vec![self, other])
+    }
+
+    pub fn eq(self, other: Expression) -> Expression {
+        self.binary(FunctionName::EQUAL, other)
+    }
+
+    pub fn not_eq(self, other: Expression) -> Expression {
+        self.binary(FunctionName::NOT_EQUAL, other)
+    }
+
+    pub fn lt(self, other: Expression) -> Expression {
+        self.binary(FunctionName::LESS_THAN, other)
+    }
+
+    pub fn lt_eq(self, other: Expression) -> Expression {
+        self.binary(FunctionName::LESS_THAN_OR_EQUAL, other)
+    }
+
+    pub fn gt(self, other: Expression) -> Expression {
+        self.binary(FunctionName::GREATER_THAN, other)
+    }
+
+    pub fn gt_eq(self, other: Expression) -> Expression {
+        self.binary(FunctionName::GREATER_THAN_OR_EQUAL, other)
+    }
+
+    pub fn and(self, other: Expression) -> Expression {
+        self.binary(FunctionName::AND, other)
+    }
+
+    pub fn or(self, other: Expression) -> Expression {
+        self.binary(FunctionName::OR, other)
+    }
+
+    /// Returns an expression matching the values against a SQL LIKE pattern.
+    pub fn like(self, pattern: &str) -> Expression {
+        self.binary(FunctionName::LIKE_PATTERN, lit(pattern))
+    }
+
+    pub fn is_null(self) -> Expression {
+        Expression::call(FunctionName::IS_NULL, vec![self])
+    }
+}
+
+macro_rules! expression_op {
+    ($op:ident, $method:ident, $function:expr) => {
+        impl std::ops::$op for Expression {
+            type Output = Expression;
+
+            fn $method(self, other: Expression) -> Expression {
+                self.binary($function, other)
+            }
+        }
+    };
+}
+
+expression_op!(Add, add, FunctionName::ADD);
+expression_op!(Sub, sub, FunctionName::SUBTRACT);
+expression_op!(Mul, mul, FunctionName::MULTIPLY);
+expression_op!(Div, div, FunctionName::DIVIDE);
+expression_op!(Rem, rem, FunctionName::MODULUS);
+
+impl std::ops::Neg for Expression {
+    type Output = Expression;
+
+    fn neg(self) -> Expression {
+        Expression::call(FunctionName::NEGATE, vec![self])
+    }
+}
+
+impl Not for Expression {
+    type Output = Expression;
+
+    fn not(self) -> Expression {
+        Expression::call(FunctionName::NOT, vec![self])
+    }
+}
+
+/// Builder for `Constraints`, created with `Constraints::builder`. All
+/// predicates added to the builder have to be satisfied. The predicates are
+/// validated against the schema of the table when the constraints are built.
+#[derive(Debug, Clone)]
+pub struct ConstraintsBuilder {
+    schema: Schema,
+    predicates: Vec<Predicate>,
+    expressions: Vec<Expression>,
+    order_by: Vec<OrderByField>,
+    limit: Option<i64>,
+}
+
+impl ConstraintsBuilder {
+    pub(crate) fn new(schema: Schema) -> Self {
+        ConstraintsBuilder {
+            schema,
+            predicates: vec![],
+            expressions: vec![],
+            order_by: vec![],
+            limit: None,
+        }
+    }
+
+    /// Adds a predicate that the rows returned by the connector must satisfy.
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// Adds an expression that the rows returned by the connector must satisfy.
+    /// Expressions are only sent to connectors supporting
+    /// `SerDeVersion::V3`, so they should be used for the predicates that
+    /// cannot be added with `filter`.
+    pub fn expression(mut self, expression: Expression) -> Self {
+        self.expressions.push(expression);
+        self
+    }
+
+    /// Appends a column to the ORDER BY clause.
+    pub fn order_by(mut self, column: &str, direction: SortDirection) -> Self {
+        self.order_by
+            .push(OrderByField::new(column.to_string(), direction));
+        self
+    }
+
+    /// Sets the maximum number of rows the connector has to return.
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Translates the predicates into a value set per column. Columns whose
+    /// values are not constrained are left out of the summary.
+    pub fn build(self) -> Result<Constraints> {
+        let mut domains = HashMap::new();
+        for predicate in &self.predicates {
+            domains = intersect_domains(domains, self.domains(&predicate.0)?);
+        }
+
+        let mut summary = HashMap::new();
+        for (column, domain) in domains {
+            if let Some(value_set) = value_set(self.data_type(&column)?, domain)? {
+                summary.insert(column, value_set);
+            }
+        }
+        for field in &self.order_by {
+            self.data_type(field.column_name())?;
+        }
+        let expression = self
+            .expressions
+            .iter()
+            .map(|e| self.federation_expression(e, None))
+            .collect::<Result<Vec<_>>>()?;
+
+        let constraints = Constraints::new(summary)
+            .with_expression(expression)
+            .with_order_by(self.order_by);
+        Ok(match self.limit {
+            Some(limit) => constraints.with_limit(limit),
+            None => constraints,
+        })
+    }
+
+    fn data_type(&self, column: &str) -> Result<&DataType> {
+        self.schema
+            .field_with_name(column)
+            .map(Field::data_type)
+            .map_err(|_| Error::Predicate(format!("Unknown column {}", column)))
+    }
+
+    /// Returns the type of the expression, or `None` for literals that take the
+    /// type of the other arguments of a function.
+    fn expression_type(&self, expr: &Expression) -> Result<Option<DataType>> {
+        match expr {
+            Expression::Variable(name) => Ok(Some(self.data_type(name)?.clone())),
+            Expression::Literal(_) => Ok(None),
+            Expression::Call(name, _) if FunctionName::new(name).is_boolean() => {
+                Ok(Some(DataType::Boolean))
+            }
+            Expression::Call(_, arguments) => self.arguments_type(arguments),
+        }
+    }
+
+    /// Returns the type of the first argument whose type is known.
+    fn arguments_type(&self, arguments: &[Expression]) -> Result<Option<DataType>> {
+        for argument in arguments {
+            if let Some(data_type) = self.expression_type(argument)? {
+                return Ok(Some(data_type));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Translates the expression, converting literals to the given type.
+    fn federation_expression(
+        &self,
+        expr: &Expression,
+        literal_hint: Option<&DataType>,
+    ) -> Result<FederationExpression> {
+        match expr {
+            Expression::Variable(name) => Ok(FederationExpression::variable(
+                name,
+                self.data_type(name)?.clone(),
+            )),
+            Expression::Literal(value) => {
+                let data_type = literal_hint.cloned().unwrap_or_else(|| literal_type(value));
+                let converted = convert(&data_type, value).ok_or_else(|| {
+                    Error::Predicate(format!(
+                        "Value {:?} cannot be converted to type {:?}",
+                        value, data_type
+                    ))
+                })?;
+                Ok(FederationExpression::constant(
+                    value_block(&data_type, &[Some(converted)])?,
+                    data_type,
+                ))
+            }
+            Expression::Call(name, arguments) => {
+                let argument_type = match self.arguments_type(arguments)? {
+                    Some(data_type) => Some(data_type),
+                    None => arguments.iter().find_map(|a| match a {
+                        Expression::Literal(value) => Some(literal_type(value)),
+                        _ => None,
+                    }),
+                };
+                let translated = arguments
+                    .iter()
+                    .map(|a| self.federation_expression(a, argument_type.as_ref()))
+                    .collect::<Result<Vec<_>>>()?;
+                let data_type = if FunctionName::new(name).is_boolean() {
+                    DataType::Boolean
+                } else {
+                    argument_type.unwrap_or(DataType::Null)
+                };
+                Ok(FederationExpression::call(name, data_type, translated))
+            }
+        }
+    }
+
+    /// Returns the domains of the columns the expression refers to.
+    fn domains(&self, expr: &Expr) -> Result<HashMap<String, Domain>> {
+        match expr {
+            Expr::Column(column, test) => {
+                Ok(single_domain(column.clone(), self.domain(column, test)?))
+            }
+            Expr::And(a, b) => Ok(intersect_domains(self.domains(a)?, self.domains(b)?)),
+            Expr::Or(a, b) => {
+                let (a, b) = (self.domains(a)?, self.domains(b)?);
+                match (into_single(a), into_single(b)) {
+                    (Some((a_column, a)), Some((b_column, b))) if a_column == b_column => {
+                        Ok(single_domain(a_column, a.union(&b)))
+                    }
+                    _ => Err(Error::Predicate(
+                        "OR is only supported for predicates on the same column".to_string(),
+                    )),
+                }
+            }
+            Expr::Not(e) => match into_single(self.domains(e)?) {
+                Some((column, domain)) => Ok(single_domain(column, domain.complement())),
+                None => Err(Error::Predicate(
+                    "NOT is only supported for predicates on a single column".to_string(),
+                )),
+            },
+        }
+    }
+
+    fn domain(&self, column: &str, test: &Test) -> Result<Domain> {
+        let data_type = self.data_type(column)?;
+        let orderable = is_orderable(column, data_type)?;
+        let convert = |value: &Scalar| coerce(column, data_type, value);
+        let convert_endpoint = |endpoint: &Endpoint| -> Result<Endpoint> {
+            match endpoint {
+                Some((value, inclusive)) => Ok(Some((convert(value)?, *inclusive))),
+                None => Ok(None),
+            }
+        };
+
+        let values = match test {
+            Test::IsNull => {
+                return Ok(Domain {
+                    values: Values::none(orderable),
+                    null_allowed: true,
+                });
+            }
+            Test::IsNotNull => Values::all(orderable),
+            Test::InList(values) => Values::of(
+                orderable,
+                values.iter().map(convert).collect::<Result<Vec<_>>>()?,
+            ),
+            Test::Range(interval) if orderable => Values::Ranges(normalize(vec![Interval {
+                low: convert_endpoint(&interval.low)?,
+                high: convert_endpoint(&interval.high)?,
+            }])),
+            Test::Range(_) => {
+                return Err(Error::Predicate(format!(
+                    "Column {} of type {:?} only supports equality predicates",
+                    column, data_type
+                )));
+            }
+        };
+        Ok(Domain {
+            values,
+            null_allowed: false,
+        })
+    }
+}
+
+fn single_domain(column: String, domain: Domain) -> HashMap<String, Domain> {
+    let mut domains = HashMap::new();
+    domains.insert(column, domain);
+    domains
+}
+
+fn into_single(domains: HashMap<String, Domain>) -> Option<(String, Domain)> {
+    if domains.len() == 1 {
+        domains.into_iter().next()
+    } else {
+        None
+    }
+}
+
+fn intersect_domains(
+    mut a: HashMap<String, Domain>,
+    b: HashMap<String, Domain>,
+) -> HashMap<String, Domain> {
+    for (column, domain) in b {
+        let domain = match a.get(&column) {
+            Some(existing) => existing.intersect(&domain),
+            None => domain,
+        };
+        a.insert(column, domain);
+    }
+    a
+}
+
+/// Returns true if the values of the type are ordered, or false if they can
+/// only be compared for equality.
+fn is_orderable(column: &str, data_type: &DataType) -> Result<bool> {
+    match data_type {
+        DataType::Boolean => Ok(false),
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Utf8
+        | DataType::Date32(_)
+        | DataType::Date64(_)
+        | DataType::Timestamp(_, None) => Ok(true),
+        _ => Err(Error::Predicate(format!(
+            "Column {} of type {:?} is not supported in predicates",
+            column, data_type
+        ))),
+    }
+}
+
+fn signed(value: &Scalar, min: i64, max: i64) -> Option<Scalar> {
+    value
+        .to_i64()
+        .filter(|v| *v >= min && *v <= max)
+        .map(Scalar::Int)
+}
+
+fn unsigned(value: &Scalar, max: u64) -> Option<Scalar> {
+    value.to_u64().filter(|v| *v <= max).map(Scalar::UInt)
+}
+
+/// Converts the literal into the representation used for values of the type.
+fn coerce(column: &str, data_type: &DataType, value: &Scalar) -> Result<Scalar> {
+    convert(data_type, value).ok_or_else(|| {
+        Error::Predicate(format!(
+            "Value {:?} cannot be compared with column {} of type {:?}",
+            value, column, data_type
+        ))
+    })
+}
+
+/// Converts the value into the representation used for values of the type,
+/// if it is a valid value of the type. NaN is rejected, because it cannot be
+/// ordered against the other values.
+fn convert(data_type: &DataType, value: &Scalar) -> Option<Scalar> {
+    let (i32_min, i32_max) = (i64::from(i32::min_value()), i64::from(i32::max_value()));
+    match data_type {
+        DataType::Boolean => value.as_bool().map(Scalar::Boolean),
+        DataType::Utf8 => value.as_str().map(Scalar::from),
+        DataType::Float32 | DataType::Float64 => {
+            value.to_f64().filter(|v| !v.is_nan()).map(Scalar::Float)
+        }
+        DataType::Int8 => signed(value, i8::min_value().into(), i8::max_value().into()),
+        DataType::Int16 => signed(value, i16::min_value().into(), i16::max_value().into()),
+        DataType::Int32 | DataType::Date32(_) => signed(value, i32_min, i32_max),
+        DataType::Int64 | DataType::Date64(_) | DataType::Timestamp(_, None) => {
+            value.to_i64().map(Scalar::Int)
+        }
+        DataType::UInt8 => unsigned(value, u8::max_value().into()),
+        DataType::UInt16 => unsigned(value, u16::max_value().into()),
+        DataType::UInt32 => unsigned(value, u32::max_value().into()),
+        DataType::UInt64 => value.to_u64().map(Scalar::UInt),
+        _ => None,
+    }
+}
+
+/// Returns the type of a literal whose type is not given by the other
+/// arguments of a function.
+fn literal_type(value: &Scalar) -> DataType {
+    match value {
+        Scalar::Boolean(_) => DataType::Boolean,
+        Scalar::Int(_) => DataType::Int64,
+        Scalar::UInt(_) => DataType::UInt64,
+        Scalar::Float(_) => DataType::Float64,
+        Scalar::Utf8(_) => DataType::Utf8,
+    }
+}
+
+/// Name of the column of the value blocks of markers and equatable value sets.
+const VALUE_COLUMN: &str = "col1";
+
+macro_rules! value_array {
+    ($array:ty, $values:expr, $convert:expr) => {
+        Arc::new(<$array>::from(
+            $values
+                .iter()
+                .map(|v| v.as_ref().and_then($convert))
+                .collect::<Vec<_>>(),
+        )) as ArrayRef
+    };
+}
+
+/// Encodes the values into a block with a single column of the given type.
+fn value_block(data_type: &DataType, values: &[Option<Scalar>]) -> Result<Block> {
+    let array = match data_type {
+        DataType::Boolean => value_array!(BooleanArray, values, |v| v.as_bool()),
+        DataType::Utf8 => value_array!(StringArray, values, |v| v.as_str()),
+        DataType::Float32 => value_array!(Float32Array, values, |v| v.to_f64().map(|v| v as f32)),
+        DataType::Float64 => value_array!(Float64Array, values, |v| v.to_f64()),
+        DataType::Int8 => value_array!(Int8Array, values, |v| v.to_i64().map(|v| v as i8)),
+        DataType::Int16 => value_array!(Int16Array, values, |v| v.to_i64().map(|v| v as i16)),
+        DataType::Int32 => value_array!(Int32Array, values, |v| v.to_i64().map(|v| v as i32)),
+        DataType::Int64 => value_array!(Int64Array, values, |v| v.to_i64()),
+        DataType::UInt8 => value_array!(UInt8Array, values, |v| v.to_u64().map(|v| v as u8)),
+        DataType::UInt16 => value_array!(UInt16Array, values, |v| v.to_u64().map(|v| v as u16)),
+        DataType::UInt32 => value_array!(UInt32Array, values, |v| v.to_u64().map(|v| v as u32)),
+        DataType::UInt64 => value_array!(UInt64Array, values, |v| v.to_u64()),
+        DataType::Date32(_) => value_array!(Date32Array, values, |v| v.to_i64().map(|v| v as i32)),
+        DataType::Date64(_) => value_array!(Date64Array, values, |v| v.to_i64()),
+        DataType::Timestamp(TimeUnit::Second, None) => {
+            value_array!(TimestampSecondArray, values, |v| v.to_i64())
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, None) => {
+            value_array!(TimestampMillisecondArray, values, |v| v.to_i64())
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            value_array!(TimestampMicrosecondArray, values, |v| v.to_i64())
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, None) => {
+            value_array!(TimestampNanosecondArray, values, |v| v.to_i64())
+        }
+        _ => {
+            return Err(Error::Predicate(format!(
+                "Values of type {:?} cannot be encoded",
+                data_type
+            )));
+        }
+    };
+
+    let schema = Schema::new(vec![Field::new(VALUE_COLUMN, data_type.clone(), true)]);
+    Block::from_record_batch(
+        RecordBatch::try_new(Arc::new(schema), vec![array])?,
+        random_allocator_id(),
+    )
+}
+
+fn marker(data_type: &DataType, endpoint: Endpoint, bound: Bound) -> Result<Marker> {
+    match endpoint {
+        Some((value, true)) => Ok(Marker::new(
+            value_block(data_type, &[Some(value)])?,
+            Bound::Exactly,
+            false,
+        )),
+        Some((value, false)) => Ok(Marker::new(
+            value_block(data_type, &[Some(value)])?,
+            bound,
+            false,
+        )),
+        None => Ok(Marker::new(value_block(data_type, &[None])?, bound, true)),
+    }
+}
+
+/// Translates the domain of a column into a value set, or `None` if the
+/// column is not constrained.
+fn value_set(data_type: &DataType, domain: Domain) -> Result<Option<ValueSet>> {
+    let Domain {
+        values,
+        null_allowed,
+    } = domain;
+    if values.is_all() && null_allowed {
+        return Ok(None);
+    }
+    if values.is_all() || values.is_none() {
+        return Ok(Some(ValueSet::AllOrNoneValueSet(AllOrNoneValueSet::new(
+            data_type.clone(),
+            values.is_all(),
+            null_allowed,
+        ))));
+    }
+
+    let value_set = match values {
+        Values::Ranges(intervals) => {
+            let ranges = intervals
+                .into_iter()
+                .map(|i| {
+                    Ok(Range::new(
+                        marker(data_type, i.low, Bound::Above)?,
+                        marker(data_type, i.high, Bound::Below)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            ValueSet::SortedRangeSet(SortedRangeSet::new(data_type.clone(), ranges, null_allowed))
+        }
+        Values::Discrete { values, white_list } => {
+            let values: Vec<_> = values.into_iter().map(Some).collect();
+            ValueSet::EquatableValueSet(EquatableValueSet::new(
+                value_block(data_type, &values)?,
+                white_list,
+                null_allowed,
+            ))
+        }
+    };
+    Ok(Some(value_set))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use serde_json::{self, json};
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("log_stream", DataType::Utf8, true),
+            Field::new("time", DataType::Int64, true),
+            Field::new("size", DataType::Float64, true),
+            Field::new("error", DataType::Boolean, true),
+            Field::new("payload", DataType::Binary, true),
+        ])
+    }
+
+    fn decoded(constraints: &Constraints, column: &str) -> ValueSet {
+        let value_set = constraints.summary()[column].clone();
+        // The encoded blocks are decoded again.
+        let json = serde_json::to_value(&value_set).unwrap();
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn ranges(value_set: ValueSet) -> Vec<Range> {
+        match value_set {
+            ValueSet::SortedRangeSet(s) => s.ranges().to_vec(),
+            v => panic!("Unexpected value set {:?}", v),
+        }
+    }
+
+    fn int_value(marker: &Marker) -> Option<i64> {
+        let column = marker.value_block().record_batch().column(0);
+        let array = column.as_any().downcast_ref::<Int64Array>().unwrap();
+        if array.is_null(0) {
+            None
+        } else {
+            Some(array.value(0))
+        }
+    }
+
+    #[test]
+    fn test_ranges() {
+        let constraints = Constraints::builder(&schema())
+            .filter(col("time").between(10, 20).or(col("time").gt_eq(15)))
+            .filter(col("time").lt(100))
+            .filter(col("size").gt(1))
+            .build()
+            .unwrap();
+        assert_eq!(2, constraints.summary().len());
+
+        let time = ranges(decoded(&constraints, "time"));
+        assert_eq!(1, time.len());
+        assert_eq!(Some(10), int_value(time[0].low()));
+        assert_eq!(Bound::Exactly, time[0].low().bound());
+        assert_eq!(Some(100), int_value(time[0].high()));
+        assert_eq!(Bound::Below, time[0].high().bound());
+
+        let size = ranges(decoded(&constraints, "size"));
+        assert_eq!(Bound::Above, size[0].low().bound());
+        assert!(size[0].high().is_upper_unbounded());
+        assert!(size[0].high().is_null_value());
+    }
+
+    #[test]
+    fn test_in_list_and_not() {
+        let constraints = Constraints::builder(&schema())
+            .filter(col("log_stream").in_list(vec!["b", "a", "b"]))
+            .filter(col("time").not_eq(5).or(col("time").is_null()))
+            .build()
+            .unwrap();
+
+        let log_stream = ranges(decoded(&constraints, "log_stream"));
+        assert_eq!(2, log_stream.len());
+        let column = log_stream[0].low().value_block().record_batch().column(0);
+        let array = column.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!("a", array.value(0));
+
+        let time = decoded(&constraints, "time");
+        assert!(time.is_null_allowed());
+        let time = ranges(time);
+        assert_eq!(2, time.len());
+        assert!(time[0].low().is_lower_unbounded());
+        assert_eq!(Some(5), int_value(time[0].high()));
+        assert_eq!(Bound::Below, time[0].high().bound());
+        assert_eq!(Bound::Above, time[1].low().bound());
+    }
+
+    #[test]
+    fn test_null_tests() {
+        let constraints = Constraints::builder(&schema())
+            .filter(col("log_stream").is_null())
+            .filter(not(col("time").is_null()))
+            .filter(col("size").is_not_null().or(col("size").is_null()))
+            .build()
+            .unwrap();
+        assert!(!constraints.summary().contains_key("size"));
+
+        match decoded(&constraints, "log_stream") {
+            ValueSet::AllOrNoneValueSet(s) => {
+                assert!(!s.is_all());
+                assert!(s.null_allowed);
+            }
+            v => panic!("Unexpected value set {:?}", v),
+        }
+        match decoded(&constraints, "time") {
+            ValueSet::AllOrNoneValueSet(s) => {
+                assert!(s.is_all());
+                assert!(!s.null_allowed);
+            }
+            v => panic!("Unexpected value set {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_equatable() {
+        let constraints = Constraints::builder(&schema())
+            .filter(col("error").not_eq(true))
+            .build()
+            .unwrap();
+
+        match decoded(&constraints, "error") {
+            ValueSet::EquatableValueSet(s) => {
+                assert!(!s.is_white_list());
+                assert_eq!(1, s.value_block().record_batch().num_rows());
+            }
+            v => panic!("Unexpected value set {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_order_by_and_limit() {
+        let constraints = Constraints::builder(&schema())
+            .order_by("time", SortDirection::DescNullsLast)
+            .limit(100)
+            .build()
+            .unwrap();
+        assert!(constraints.summary().is_empty());
+        assert_eq!(Some(100), constraints.limit());
+        assert_eq!("time", constraints.order_by()[0].column_name());
+
+        let json = serde_json::to_value(&constraints).unwrap();
+        assert_eq!(
+            json!({
+                "summary": {},
+                "orderByClause": [{ "columnName": "time", "direction": "DESC_NULLS_LAST" }],
+                "limit": 100
+            }),
+            json
+        );
+
+        let unknown = Constraints::builder(&schema())
+            .order_by("unknown", SortDirection::AscNullsFirst)
+            .build();
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn test_expressions() {
+        let constraints = Constraints::builder(&schema())
+            .expression(var("log_stream").like("%error%"))
+            .expression(
+                (var("time") + lit(1))
+                    .gt(lit(10))
+                    .or(var("size").lt(lit(5))),
+            )
+            .build()
+            .unwrap();
+        assert!(constraints.summary().is_empty());
+        assert_eq!(2, constraints.expression().len());
+
+        let json = serde_json::to_value(&constraints).unwrap();
+        let like = &json["expression"][0];
+        assert_eq!("FunctionCallExpression", like["@type"]);
+        assert_eq!("$like_pattern", like["functionName"]["functionName"]);
+        assert_eq!("bool", like["type"]["name"]);
+        assert_eq!("VariableExpression", like["arguments"][0]["@type"]);
+        assert_eq!("log_stream", like["arguments"][0]["columnName"]);
+        assert_eq!("ConstantExpression", like["arguments"][1]["@type"]);
+        assert_eq!("utf8", like["arguments"][1]["type"]["name"]);
+
+        let or = &json["expression"][1];
+        assert_eq!("$or", or["functionName"]["functionName"]);
+        let add = &or["arguments"][0]["arguments"][0];
+        assert_eq!("$add", add["functionName"]["functionName"]);
+        assert_eq!(64, add["type"]["bitWidth"]);
+        // Literals take the type of the column they are compared with
+        let size = &or["arguments"][1]["arguments"][1];
+        assert_eq!("floatingpoint", size["type"]["name"]);
+
+        let decoded: Constraints = serde_json::from_value(json).unwrap();
+        match &decoded.expression()[0] {
+            FederationExpression::FunctionCallExpression(e) => {
+                assert_eq!(FunctionName::LIKE_PATTERN, e.function_name().name());
+                assert_eq!(2, e.arguments().len());
+            }
+            e => panic!("Unexpected expression {:?}", e),
+        }
+
+        let invalid = Constraints::builder(&schema())
+            .expression(var("time").eq(lit("a")))
+            .build();
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn test_invalid_predicates() {
+        let invalid = vec![
+            col("unknown").eq(1),
+            col("time").eq("a"),
+            col("log_stream").lt(1),
+            col("error").gt(false),
+            col("payload").eq("a"),
+            col("size").eq(std::f64::NAN),
+            col("size").between(0.0, std::f64::NAN),
+            col("time").eq(1).or(col("size").eq(1)),
+            !col("time").eq(1).and(col("size").eq(1)),
+        ];
+        for predicate in invalid {
+            match Constraints::builder(&schema()).filter(predicate).build() {
+                Err(Error::Predicate(_)) => {}
+                r => panic!("Unexpected result {:?}", r),
+            }
+        }
+    }
+}
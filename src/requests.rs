@@ -15,25 +15,32 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::default::Default;
 
 // Include the model classes
+use super::models::ipc_for_version;
 use super::models::*;
 
 /// Helper macro that generates the necessary stringification for the @type attribute
-/// of the API requests.
+/// of the API requests. If the Java class name differs from the Rust struct name, the
+/// name can be passed as the second argument.
 macro_rules! class_type_def {
     ($x:ident) => {
+        class_type_def!($x, stringify!($x));
+    };
+    ($x:ident, $name:expr) => {
         impl $x {
             fn class_type_def() -> String {
-                stringify!($x).to_string()
+                $name.to_string()
             }
         }
     };
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ReadRecordRequest {
     catalog_name: String,
@@ -46,8 +53,12 @@ pub struct ReadRecordRequest {
     max_block_size: i64,
     max_inline_block_size: i64,
     request_type: String,
+    #[serde(rename = "@type", default = "ReadRecordRequest::class_type_def")]
+    class_type: String,
 }
 
+class_type_def!(ReadRecordRequest, "ReadRecordsRequest");
+
 impl Default for ReadRecordRequest {
     fn default() -> Self {
         ReadRecordRequest {
@@ -61,51 +72,147 @@ impl Default for ReadRecordRequest {
             max_block_size: 16000000,
             max_inline_block_size: 5242880,
             request_type: String::from("READ_RECORDS"),
+            class_type: Self::class_type_def(),
         }
     }
 }
 
-#[derive(Debug, Default, Serialize)]
+impl ReadRecordRequest {
+    /// Creates a new request to read the records of a single `Split`. The block
+    /// size limits are initialized with the defaults of the SDK.
+    pub fn new(
+        query_id: String,
+        catalog_name: String,
+        table_name: TableName,
+        schema: Schema,
+        split: Split,
+        constraints: Constraints,
+    ) -> Self {
+        ReadRecordRequest {
+            catalog_name,
+            query_id,
+            table_name,
+            schema,
+            split,
+            constraints,
+            ..Default::default()
+        }
+    }
+
+    /// Returns a copy of the request that reads the records of the given split.
+    pub fn with_split(&self, split: Split) -> Self {
+        ReadRecordRequest {
+            split,
+            ..self.clone()
+        }
+    }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    /// Sets the limits for the size of the blocks written by the lambda function.
+    pub(crate) fn set_block_sizes(&mut self, max_block_size: i64, max_inline_block_size: i64) {
+        self.max_block_size = max_block_size;
+        self.max_inline_block_size = max_inline_block_size;
+    }
+
+    /// Sets the key the lambda function has to use to encrypt the blocks it
+    /// spills for this request.
+    pub fn set_encryption_key(&mut self, key: EncryptionKey) {
+        self.split.set_encryption_key(key);
+    }
+}
+
+/// Response of the lambda function when the result of a `ReadRecordRequest`
+/// fits into the payload of the invocation.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadRecordsResponse {
+    #[serde(rename = "@type", default = "ReadRecordsResponse::class_type_def")]
+    class_type: String,
+    request_type: String,
+
+    pub catalog_name: String,
+    pub records: Block,
+}
+
+class_type_def!(ReadRecordsResponse);
+
+/// Response of the lambda function when the result of a `ReadRecordRequest`
+/// exceeded the `max_inline_block_size` and was spilled to S3. The blocks
+/// have to be fetched from the `remote_blocks` locations and decrypted with
+/// the `encryption_key` if present.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteReadRecordsResponse {
+    #[serde(
+        rename = "@type",
+        default = "RemoteReadRecordsResponse::class_type_def"
+    )]
+    class_type: String,
+    request_type: String,
+
+    pub catalog_name: String,
+    pub schema: Schema,
+    pub remote_blocks: Vec<SpillLocation>,
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+class_type_def!(RemoteReadRecordsResponse);
+
+/// The lambda function answers a `ReadRecordRequest` either with the inline
+/// records or with the locations of the spilled records. The variant is
+/// selected based on the `@type` attribute of the response.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "@type")]
+pub enum RecordsResponse {
+    ReadRecordsResponse(ReadRecordsResponse),
+    RemoteReadRecordsResponse(RemoteReadRecordsResponse),
+}
+
+impl RecordsResponse {
+    /// Returns true if the records of the split were spilled to S3.
+    pub fn is_spilled(&self) -> bool {
+        match self {
+            RecordsResponse::RemoteReadRecordsResponse(_) => true,
+            RecordsResponse::ReadRecordsResponse(_) => false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListSchemasRequest {
     identity: FederatedIdentity,
     query_id: String,
     catalog_name: String,
-    #[serde(
-        rename(serialize = "@type"),
-        default = "ListSchemasRequest::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "ListSchemasRequest::class_type_def")]
     class_type: String,
 }
 
 class_type_def!(ListSchemasRequest);
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListSchemasResponse {
     pub catalog_name: String,
     request_type: String,
     pub schemas: Vec<String>,
-    #[serde(
-        rename(deserialize = "@type"),
-        default = "ListSchemasResponse::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "ListSchemasResponse::class_type_def")]
     class_type: String,
 }
 
 class_type_def!(ListSchemasResponse);
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListTablesRequest {
     identity: FederatedIdentity,
     query_id: String,
     catalog_name: String,
     schema_name: String,
-    #[serde(
-        rename(serialize = "@type"),
-        default = "ListTablesRequest::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "ListTablesRequest::class_type_def")]
     class_type: String,
 }
 
@@ -123,13 +230,10 @@ impl ListTablesRequest {
     }
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ListTablesResponse {
-    #[serde(
-        rename(deserialize = "@type"),
-        default = "ListTablesResponse::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "ListTablesResponse::class_type_def")]
     class_type: String,
 
     pub catalog_name: String,
@@ -139,18 +243,17 @@ pub struct ListTablesResponse {
 
 class_type_def!(ListTablesResponse);
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTableRequest {
     identity: FederatedIdentity,
     query_id: String,
     catalog_name: String,
     table_name: TableName,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    query_passthrough_arguments: HashMap<String, String>,
 
-    #[serde(
-        rename(serialize = "@type"),
-        default = "GetTableRequest::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetTableRequest::class_type_def")]
     class_type: String,
 }
 
@@ -163,18 +266,23 @@ impl GetTableRequest {
             catalog_name: catalog_name,
             query_id: String::new(),
             table_name: TableName::new(schema_name, table_name),
+            query_passthrough_arguments: HashMap::new(),
             class_type: Self::class_type_def(),
         }
     }
+
+    /// Sets the arguments of the query passthrough function, so that the
+    /// connector returns the schema of the native query.
+    pub fn with_query_passthrough_arguments(mut self, arguments: HashMap<String, String>) -> Self {
+        self.query_passthrough_arguments = arguments;
+        self
+    }
 }
 
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTableResponse {
-    #[serde(
-        rename(deserialize = "@type"),
-        default = "GetTableResponse::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetTableResponse::class_type_def")]
     class_type: String,
 
     pub catalog_name: String,
@@ -185,7 +293,7 @@ pub struct GetTableResponse {
 
 class_type_def!(GetTableResponse);
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTableLayoutRequest {
     identity: FederatedIdentity,
@@ -197,10 +305,7 @@ pub struct GetTableLayoutRequest {
     // Is a set
     partition_cols: Vec<String>,
 
-    #[serde(
-        rename(serialize = "@type"),
-        default = "GetTableLayoutRequest::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetTableLayoutRequest::class_type_def")]
     class_type: String,
 }
 
@@ -229,13 +334,10 @@ impl GetTableLayoutRequest {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetTableLayoutResponse {
-    #[serde(
-        rename(deserialize = "@type"),
-        default = "GetTableLayoutResponse::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetTableLayoutResponse::class_type_def")]
     class_type: String,
     request_type: String,
 
@@ -251,10 +353,7 @@ class_type_def!(GetTableLayoutResponse);
 pub struct GetSplitsRequest {
     #[serde(default)]
     identity: FederatedIdentity,
-    #[serde(
-        rename(serialize = "@type"),
-        default = "GetSplitsRequest::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetSplitsRequest::class_type_def")]
     class_type: String,
     query_id: String,
     catalog_name: String,
@@ -295,20 +394,478 @@ impl GetSplitsRequest {
 #[derive(Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GetSplitsResponse {
-    #[serde(
-        rename(deserialize = "@type"),
-        default = "GetSplitsResponse::class_type_def"
-    )]
+    #[serde(rename = "@type", default = "GetSplitsResponse::class_type_def")]
     class_type: String,
     request_type: String,
+    catalog_name: String,
+    splits: Vec<Split>,
+    continuation_token: Option<String>,
 }
 
 class_type_def!(GetSplitsResponse);
 
+impl GetSplitsResponse {
+    pub fn catalog_name(&self) -> &str {
+        &self.catalog_name
+    }
+
+    pub fn splits(&self) -> &[Split] {
+        &self.splits
+    }
+
+    /// Consumes the response and returns the splits to pass to the
+    /// `ReadRecordRequest`s.
+    pub fn into_splits(self) -> Vec<Split> {
+        self.splits
+    }
+
+    /// Returns the token to fetch the next page of splits. If the token is
+    /// `None`, all splits have been returned.
+    pub fn continuation_token(&self) -> Option<&str> {
+        self.continuation_token.as_ref().map(String::as_str)
+    }
+}
+
+/// Request to check that the connector is reachable.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PingRequest {
+    identity: FederatedIdentity,
+    query_id: String,
+    catalog_name: String,
+    #[serde(rename = "@type", default = "PingRequest::class_type_def")]
+    class_type: String,
+}
+
+class_type_def!(PingRequest);
+
+impl PingRequest {
+    pub fn new(query_id: String, catalog_name: String) -> Self {
+        PingRequest {
+            query_id,
+            catalog_name,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PingResponse {
+    #[serde(rename = "@type", default = "PingResponse::class_type_def")]
+    class_type: String,
+
+    pub catalog_name: String,
+    pub query_id: String,
+    pub source_type: String,
+    /// Bitmask of the optional features supported by the connector.
+    #[serde(default)]
+    pub capabilities: i32,
+    /// The latest wire format supported by the connector.
+    #[serde(default)]
+    pub ser_de_version: SerDeVersion,
+}
+
+class_type_def!(PingResponse);
+
+/// Request for the optimizations supported by the connector.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetDataSourceCapabilitiesRequest {
+    identity: FederatedIdentity,
+    query_id: String,
+    catalog_name: String,
+    #[serde(
+        rename = "@type",
+        default = "GetDataSourceCapabilitiesRequest::class_type_def"
+    )]
+    class_type: String,
+}
+
+class_type_def!(GetDataSourceCapabilitiesRequest);
+
+impl GetDataSourceCapabilitiesRequest {
+    pub fn new(query_id: String, catalog_name: String) -> Self {
+        GetDataSourceCapabilitiesRequest {
+            query_id,
+            catalog_name,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetDataSourceCapabilitiesResponse {
+    #[serde(
+        rename = "@type",
+        default = "GetDataSourceCapabilitiesResponse::class_type_def"
+    )]
+    class_type: String,
+
+    pub catalog_name: String,
+    /// The supported optimizations by name, with the supported variants.
+    #[serde(default)]
+    pub capabilities: HashMap<String, Vec<OptimizationSubType>>,
+}
+
+class_type_def!(GetDataSourceCapabilitiesResponse);
+
+impl From<GetDataSourceCapabilitiesResponse> for Capabilities {
+    fn from(res: GetDataSourceCapabilitiesResponse) -> Self {
+        Capabilities::new(res.capabilities)
+    }
+}
+
+/// Request to evaluate a user defined function on the rows of the input
+/// records.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDefinedFunctionRequest {
+    identity: FederatedIdentity,
+    input_records: Block,
+    output_schema: Schema,
+    method_name: String,
+    function_type: String,
+    #[serde(
+        rename = "@type",
+        default = "UserDefinedFunctionRequest::class_type_def"
+    )]
+    class_type: String,
+}
+
+class_type_def!(UserDefinedFunctionRequest);
+
+impl UserDefinedFunctionRequest {
+    /// Function type of functions that return one value per input row.
+    pub const SCALAR: &'static str = "SCALAR";
+
+    pub fn new(
+        input_records: Block,
+        output_schema: Schema,
+        method_name: String,
+        function_type: String,
+    ) -> Self {
+        UserDefinedFunctionRequest {
+            identity: FederatedIdentity::default(),
+            input_records,
+            output_schema,
+            method_name,
+            function_type,
+            class_type: UserDefinedFunctionRequest::class_type_def(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserDefinedFunctionResponse {
+    #[serde(
+        rename = "@type",
+        default = "UserDefinedFunctionResponse::class_type_def"
+    )]
+    class_type: String,
+
+    pub records: Block,
+    pub method_name: String,
+}
+
+class_type_def!(UserDefinedFunctionResponse);
+
+/// Any request sent to a lambda function, selected based on the `@type`
+/// attribute. This allows to parse payloads without knowing their type, e.g.
+/// to log or replay them.
+///
+/// The request structs serialize the `@type` attribute themselves, so the
+/// enum is serialized by delegating to the wrapped request.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "@type")]
+pub enum FederationRequest {
+    #[serde(rename = "ListSchemasRequest")]
+    ListSchemas(ListSchemasRequest),
+    #[serde(rename = "ListTablesRequest")]
+    ListTables(ListTablesRequest),
+    #[serde(rename = "GetTableRequest")]
+    GetTable(GetTableRequest),
+    #[serde(rename = "GetTableLayoutRequest")]
+    GetTableLayout(GetTableLayoutRequest),
+    #[serde(rename = "GetSplitsRequest")]
+    GetSplits(GetSplitsRequest),
+    #[serde(rename = "ReadRecordsRequest")]
+    ReadRecords(ReadRecordRequest),
+    #[serde(rename = "PingRequest")]
+    Ping(PingRequest),
+    #[serde(rename = "UserDefinedFunctionRequest")]
+    UserDefinedFunction(UserDefinedFunctionRequest),
+    #[serde(rename = "GetDataSourceCapabilitiesRequest")]
+    GetDataSourceCapabilities(GetDataSourceCapabilitiesRequest),
+}
+
+impl Serialize for FederationRequest {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            FederationRequest::ListSchemas(r) => r.serialize(serializer),
+            FederationRequest::ListTables(r) => r.serialize(serializer),
+            FederationRequest::GetTable(r) => r.serialize(serializer),
+            FederationRequest::GetTableLayout(r) => r.serialize(serializer),
+            FederationRequest::GetSplits(r) => r.serialize(serializer),
+            FederationRequest::ReadRecords(r) => r.serialize(serializer),
+            FederationRequest::Ping(r) => r.serialize(serializer),
+            FederationRequest::UserDefinedFunction(r) => r.serialize(serializer),
+            FederationRequest::GetDataSourceCapabilities(r) => r.serialize(serializer),
+        }
+    }
+}
+
+/// Any response returned by a lambda function, selected based on the `@type`
+/// attribute.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "@type")]
+pub enum FederationResponse {
+    #[serde(rename = "ListSchemasResponse")]
+    ListSchemas(ListSchemasResponse),
+    #[serde(rename = "ListTablesResponse")]
+    ListTables(ListTablesResponse),
+    #[serde(rename = "GetTableResponse")]
+    GetTable(GetTableResponse),
+    #[serde(rename = "GetTableLayoutResponse")]
+    GetTableLayout(GetTableLayoutResponse),
+    #[serde(rename = "GetSplitsResponse")]
+    GetSplits(GetSplitsResponse),
+    #[serde(rename = "ReadRecordsResponse")]
+    ReadRecords(ReadRecordsResponse),
+    #[serde(rename = "RemoteReadRecordsResponse")]
+    RemoteReadRecords(RemoteReadRecordsResponse),
+    #[serde(rename = "PingResponse")]
+    Ping(PingResponse),
+    #[serde(rename = "UserDefinedFunctionResponse")]
+    UserDefinedFunction(UserDefinedFunctionResponse),
+    #[serde(rename = "GetDataSourceCapabilitiesResponse")]
+    GetDataSourceCapabilities(GetDataSourceCapabilitiesResponse),
+}
+
+impl Serialize for FederationResponse {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            FederationResponse::ListSchemas(r) => r.serialize(serializer),
+            FederationResponse::ListTables(r) => r.serialize(serializer),
+            FederationResponse::GetTable(r) => r.serialize(serializer),
+            FederationResponse::GetTableLayout(r) => r.serialize(serializer),
+            FederationResponse::GetSplits(r) => r.serialize(serializer),
+            FederationResponse::ReadRecords(r) => r.serialize(serializer),
+            FederationResponse::RemoteReadRecords(r) => r.serialize(serializer),
+            FederationResponse::Ping(r) => r.serialize(serializer),
+            FederationResponse::UserDefinedFunction(r) => r.serialize(serializer),
+            FederationResponse::GetDataSourceCapabilities(r) => r.serialize(serializer),
+        }
+    }
+}
+
+impl From<RecordsResponse> for FederationResponse {
+    fn from(res: RecordsResponse) -> Self {
+        match res {
+            RecordsResponse::ReadRecordsResponse(r) => FederationResponse::ReadRecords(r),
+            RecordsResponse::RemoteReadRecordsResponse(r) => {
+                FederationResponse::RemoteReadRecords(r)
+            }
+        }
+    }
+}
+
+/// A request sent to a lambda function. Every request downgrades the fields
+/// that changed between the versions of the wire format itself, so connectors
+/// built with an older SDK receive the format they understand.
+pub trait Request: Serialize {
+    /// Rewrites the serialized request into the format of the given version.
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()>;
+}
+
+impl Request for ListSchemasRequest {
+    fn downgrade(&self, _: &mut Value, _: SerDeVersion) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Request for ListTablesRequest {
+    fn downgrade(&self, _: &mut Value, _: SerDeVersion) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Request for GetTableRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        if version < SerDeVersion::V5 {
+            reject_passthrough(value, version)?;
+        }
+        Ok(())
+    }
+}
+
+impl Request for GetTableLayoutRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        downgrade_ipc(value.get_mut("schema"), version)?;
+        downgrade_constraints(value.get_mut("constraints"), version)
+    }
+}
+
+impl Request for GetSplitsRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        downgrade_ipc(value.get_mut("partitions"), version)?;
+        downgrade_constraints(value.get_mut("constraints"), version)
+    }
+}
+
+impl Request for ReadRecordRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        downgrade_ipc(value.get_mut("schema"), version)?;
+        downgrade_constraints(value.get_mut("constraints"), version)
+    }
+}
+
+impl Request for PingRequest {
+    fn downgrade(&self, _: &mut Value, _: SerDeVersion) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Request for GetDataSourceCapabilitiesRequest {
+    fn downgrade(&self, _: &mut Value, _: SerDeVersion) -> crate::error::Result<()> {
+        Ok(())
+    }
+}
+
+impl Request for UserDefinedFunctionRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        downgrade_ipc(value.get_mut("inputRecords"), version)?;
+        downgrade_ipc(value.get_mut("outputSchema"), version)
+    }
+}
+
+impl Request for FederationRequest {
+    fn downgrade(&self, value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+        match self {
+            FederationRequest::ListSchemas(r) => r.downgrade(value, version),
+            FederationRequest::ListTables(r) => r.downgrade(value, version),
+            FederationRequest::GetTable(r) => r.downgrade(value, version),
+            FederationRequest::GetTableLayout(r) => r.downgrade(value, version),
+            FederationRequest::GetSplits(r) => r.downgrade(value, version),
+            FederationRequest::ReadRecords(r) => r.downgrade(value, version),
+            FederationRequest::Ping(r) => r.downgrade(value, version),
+            FederationRequest::UserDefinedFunction(r) => r.downgrade(value, version),
+            FederationRequest::GetDataSourceCapabilities(r) => r.downgrade(value, version),
+        }
+    }
+}
+
+/// Serializes the request into the payload for a connector using the given
+/// wire format. Blocks and schemas are converted into the Arrow IPC format of
+/// the version.
+pub fn to_payload<R>(req: &R, version: SerDeVersion) -> crate::error::Result<Vec<u8>>
+where
+    R: Request,
+{
+    let mut value = serde_json::to_value(req)?;
+    if version < SerDeVersion::LATEST {
+        req.downgrade(&mut value, version)?;
+    }
+    Ok(serde_json::to_vec(&value)?)
+}
+
+fn remove_fields(value: &mut Value, fields: &[&str]) {
+    if let Some(map) = value.as_object_mut() {
+        for field in fields {
+            map.remove(*field);
+        }
+    }
+}
+
+/// Fails if the serialized request or constraints carry the arguments of a
+/// query passthrough function. Connectors of versions before
+/// `SerDeVersion::V5` would ignore them and run the query on the table.
+fn reject_passthrough(value: &Value, version: SerDeVersion) -> crate::error::Result<()> {
+    match value.get("queryPassthroughArguments") {
+        Some(_) => Err(crate::error::Error::Unsupported(format!(
+            "Query passthrough is not supported by connectors of version {}",
+            version.value()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Converts the IPC messages of a serialized `Block` or `Schema`.
+fn downgrade_ipc(value: Option<&mut Value>, version: SerDeVersion) -> crate::error::Result<()> {
+    if let Some(value) = value {
+        for key in &["schema", "records"] {
+            if let Some(Value::String(message)) = value.get_mut(*key) {
+                *message = ipc_for_version(message, version)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the parts of the serialized `Constraints` the version does not
+/// support and converts the value blocks of the summary. The constants of
+/// the expressions are not converted, because expressions are only sent to
+/// connectors using the current IPC format. Query passthrough arguments
+/// cannot be removed and fail the request instead.
+fn downgrade_constraints(
+    value: Option<&mut Value>,
+    version: SerDeVersion,
+) -> crate::error::Result<()> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    if version < SerDeVersion::V3 {
+        remove_fields(value, &["expression"]);
+    }
+    if version < SerDeVersion::V4 {
+        remove_fields(value, &["orderByClause", "limit"]);
+    }
+    if version < SerDeVersion::V5 {
+        reject_passthrough(value, version)?;
+    }
+
+    if let Some(Value::Object(summary)) = value.get_mut("summary") {
+        for value_set in summary.values_mut() {
+            downgrade_value_set(value_set, version)?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts the value blocks of a serialized `ValueSet`.
+fn downgrade_value_set(value: &mut Value, version: SerDeVersion) -> crate::error::Result<()> {
+    match value["@type"].as_str() {
+        Some("SortedRangeSet") => {
+            if let Some(Value::Array(ranges)) = value.get_mut("ranges") {
+                for range in ranges {
+                    for marker in &["low", "high"] {
+                        let block = range.get_mut(*marker).and_then(|m| m.get_mut("valueBlock"));
+                        downgrade_ipc(block, version)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some("EquatableValueSet") => downgrade_ipc(value.get_mut("valueBlock"), version),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::test_util::block_json;
+    use arrow::datatypes::DataType;
 
     #[test]
     fn test_class_type_macro() {
@@ -374,12 +931,344 @@ mod test {
         let val: serde_json::Value = serde_json::from_str(&json).unwrap();
         let req: GetSplitsRequest = serde_json::from_str(&json).unwrap();
 
-        let new_val = serde_json::to_value(req).unwrap();
+        let new_val = serde_json::to_value(&req).unwrap();
         assert_eq!(val, new_val);
+
+        // Connectors of the first version expect the IPC messages without the
+        // continuation marker
+        let payload = to_payload(&req, SerDeVersion::V1).unwrap();
+        let legacy: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        for key in &["schema", "records"] {
+            let message = base64::decode(legacy["partitions"][key].as_str().unwrap()).unwrap();
+            assert!(!message.starts_with(&[0xff; 4]));
+        }
+        assert_eq!(val["constraints"], legacy["constraints"]);
+    }
+
+    #[test]
+    fn test_payload_value_sets() {
+        let block = block_json();
+        let constraints: Constraints = serde_json::from_value(serde_json::json!({
+            "summary": {
+                "log_stream": {
+                    "@type": "EquatableValueSet",
+                    "valueBlock": block,
+                    "whiteList": true,
+                    "nullAllowed": false
+                },
+                "time": {
+                    "@type": "SortedRangeSet",
+                    "type": { "name": "int", "bitWidth": 64, "isSigned": true },
+                    "ranges": [{
+                        "low": { "valueBlock": block, "bound": "EXACTLY", "nullValue": false },
+                        "high": { "valueBlock": block, "bound": "BELOW", "nullValue": true }
+                    }],
+                    "nullAllowed": false
+                }
+            }
+        }))
+        .unwrap();
+        let req = ReadRecordRequest::new(
+            "query_id".to_string(),
+            "catalog".to_string(),
+            TableName::default(),
+            Schema::default(),
+            Split::default(),
+            constraints,
+        );
+        let has_marker = |message: &Value| {
+            base64::decode(message.as_str().unwrap())
+                .unwrap()
+                .starts_with(&[0xff; 4])
+        };
+
+        let payload = to_payload(&req, SerDeVersion::V2).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        let summary = &val["constraints"]["summary"];
+        assert!(has_marker(&summary["log_stream"]["valueBlock"]["records"]));
+
+        let payload = to_payload(&req, SerDeVersion::V1).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        let summary = &val["constraints"]["summary"];
+        let range = &summary["time"]["ranges"][0];
+        for block in &[
+            &summary["log_stream"]["valueBlock"],
+            &range["low"]["valueBlock"],
+            &range["high"]["valueBlock"],
+        ] {
+            assert!(!has_marker(&block["schema"]));
+            assert!(!has_marker(&block["records"]));
+        }
+    }
+
+    #[test]
+    fn test_read_records_response() {
+        let json = r#"{
+                "@type": "ReadRecordsResponse",
+                "catalogName": "catalog_name",
+                "requestType": "READ_RECORDS",
+                "records": {
+                    "schema": "/////xABAAAQAAAAAAAKAA4ABgANAAgACgAAAAAAAwAQAAAAAAEKAAwAAAAIAAQACgAAAAgAAAAIAAAAAAAAAAMAAACcAAAAPAAAAAQAAACC////FAAAABQAAAAUAAAAAAAFARAAAAAAAAAAAAAAAHD///8JAAAAbG9nX2dyb3VwAAAAtv///xQAAAAUAAAAHAAAAAAAAgEgAAAAAAAAAAAAAAAIAAwACAAHAAgAAAAAAAABQAAAABAAAABsb2dfc3RyZWFtX2J5dGVzAAASABgAFAATABIADAAAAAgABAASAAAAFAAAABQAAAAYAAAAAAAFARQAAAAAAAAAAAAAAAQABAAEAAAACgAAAGxvZ19zdHJlYW0AAA==",
+                    "records": "/////wgBAAAUAAAAAAAAAAwAFgAOABUAEAAEAAwAAACAAAAAAAAAAAAAAwAQAAAAAAMKABgADAAIAAQACgAAABQAAACYAAAAAQAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAQAAAAAAAAAIAAAAAAAAAAgAAAAAAAAAEAAAAAAAAAA0AAAAAAAAAEgAAAAAAAAAAQAAAAAAAABQAAAAAAAAAAgAAAAAAAAAWAAAAAAAAAABAAAAAAAAAGAAAAAAAAAACAAAAAAAAABoAAAAAAAAABIAAAAAAAAAAAAAAAMAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAA0AAAAMjAxOS8xMS8xNi9bJExBVEVTVF0wNTM0NmI2MTExMWI0YWQ2OTZkOTRiYTYwZTQ3MzRiNgAAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAEgAAAC9hd3MvbGFtYmRhL2N3dGVzdAAAAAAAAA==",
+                    "aId": "52fb8f5f-e2d0-4345-84d4-5f651bee361b"
+                }
+            }"#;
+
+        let res: ReadRecordsResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!("catalog_name".to_string(), res.catalog_name);
+        let batch = res.records.into_record_batch();
+        assert_eq!(1, batch.num_rows());
+        assert_eq!(3, batch.num_columns());
+    }
+
+    #[test]
+    fn test_records_response_dispatch() {
+        let json = r#"{
+                "@type": "RemoteReadRecordsResponse",
+                "catalogName": "catalog_name",
+                "requestType": "READ_RECORDS",
+                "schema": {
+                    "schema": "/////0ABAAAQAAAAAAAKAA4ABgANAAgACgAAAAAAAwAQAAAAAAEKAAwAAAAIAAQACgAAAAgAAABEAAAAAQAAAAwAAAAIAAwACAAEAAgAAAAIAAAAFAAAAAoAAABsb2dfc3RyZWFtAAANAAAAcGFydGl0aW9uQ29scwAAAAMAAACMAAAAOAAAAAQAAACS////FAAAABQAAAAUAAAAAAAFARAAAAAAAAAAAAAAAID///8HAAAAbWVzc2FnZQDC////FAAAABQAAAAcAAAAAAACASAAAAAAAAAAAAAAAAgADAAIAAcACAAAAAAAAAFAAAAABAAAAHRpbWUAABIAGAAUABMAEgAMAAAACAAEABIAAAAUAAAAFAAAABgAAAAAAAUBFAAAAAAAAAAAAAAABAAEAAQAAAAKAAAAbG9nX3N0cmVhbQAAAAAAAA=="
+                },
+                "remoteBlocks": [
+                    {
+                        "@type": "S3SpillLocation",
+                        "bucket": "magrund-ath-fed",
+                        "key": "athena-spill/e8300bd6-0737-4dfc-9af3-552fe160054f/0",
+                        "directory": false
+                    }
+                ],
+                "encryptionKey": null
+            }"#;
+
+        let res: RecordsResponse = serde_json::from_str(&json).unwrap();
+        assert!(res.is_spilled());
+        match res {
+            RecordsResponse::RemoteReadRecordsResponse(r) => {
+                assert_eq!(1, r.remote_blocks.len());
+                assert!(r.encryption_key.is_none());
+                assert_eq!("RemoteReadRecordsResponse".to_string(), r.class_type);
+            }
+            _ => panic!("Expected a RemoteReadRecordsResponse"),
+        }
+    }
+
+    #[test]
+    fn test_read_record_request_type() {
+        let r = ReadRecordRequest::default();
+        let val = serde_json::to_value(&r).unwrap();
+        assert_eq!("ReadRecordsRequest", val["@type"]);
     }
 
     #[test]
     fn test_get_split_response() {
-        unimplemented!();
+        let json = r#"{
+                "@type": "GetSplitsResponse",
+                "requestType": "GET_SPLITS",
+                "catalogName": "catalog_name",
+                "splits": [
+                    {
+                        "spillLocation": {
+                            "@type": "S3SpillLocation",
+                            "bucket": "magrund-ath-fed",
+                            "key": "athena-spill//e8300bd6-0737-4dfc-9af3-552fe160054f",
+                            "directory": true
+                        },
+                        "encryptionKey": null,
+                        "properties": {
+                            "log_group": "/aws/lambda/cwtest",
+                            "log_stream_bytes": "0",
+                            "log_stream": "2019/11/16/[$LATEST]05346b61111b4ad696d94ba60e4734b6"
+                        }
+                    }
+                ],
+                "continuationToken": "abc"
+            }"#;
+
+        let res: GetSplitsResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!("catalog_name", res.catalog_name());
+        assert_eq!(Some("abc"), res.continuation_token());
+        assert_eq!(1, res.splits().len());
+
+        let json = r#"{
+                "@type": "GetSplitsResponse",
+                "requestType": "GET_SPLITS",
+                "catalogName": "catalog_name",
+                "splits": [],
+                "continuationToken": null
+            }"#;
+        let res: GetSplitsResponse = serde_json::from_str(&json).unwrap();
+        assert!(res.continuation_token().is_none());
+        assert!(res.into_splits().is_empty());
+    }
+
+    #[test]
+    fn test_federation_request() {
+        let req = GetTableRequest::new(
+            "catalog".to_string(),
+            "schema".to_string(),
+            "table".to_string(),
+        );
+        let json = serde_json::to_string(&req).unwrap();
+        match serde_json::from_str::<FederationRequest>(&json).unwrap() {
+            FederationRequest::GetTable(r) => {
+                assert_eq!("catalog", r.catalog_name);
+                assert_eq!("GetTableRequest", r.class_type);
+            }
+            r => panic!("Unexpected request {:?}", r),
+        }
+
+        // Serializing the enum emits the @type attribute only once
+        let req: FederationRequest = serde_json::from_value(serde_json::json!({
+            "@type": "PingRequest",
+            "identity": FederatedIdentity::default(),
+            "queryId": "query_id",
+            "catalogName": "catalog"
+        }))
+        .unwrap();
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(1, json.matches("@type").count());
+        let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!("PingRequest", val["@type"]);
+        assert_eq!("query_id", val["queryId"]);
+
+        let req = FederationRequest::ReadRecords(ReadRecordRequest::default());
+        let val = serde_json::to_value(&req).unwrap();
+        assert_eq!("ReadRecordsRequest", val["@type"]);
+        assert!(serde_json::from_value::<FederationRequest>(val).is_ok());
+    }
+
+    #[test]
+    fn test_payload_constraints() {
+        let order_by = OrderByField::new("time".to_string(), SortDirection::DescNullsFirst);
+        let expression = FederationExpression::variable("error", DataType::Boolean);
+        let constraints = Constraints::default()
+            .with_expression(vec![expression])
+            .with_order_by(vec![order_by])
+            .with_limit(100);
+        let req = ReadRecordRequest::new(
+            "query_id".to_string(),
+            "catalog".to_string(),
+            TableName::default(),
+            Schema::default(),
+            Split::default(),
+            constraints,
+        );
+
+        let payload = to_payload(&req, SerDeVersion::LATEST).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(100, val["constraints"]["limit"]);
+        assert_eq!("time", val["constraints"]["orderByClause"][0]["columnName"]);
+        assert_eq!("error", val["constraints"]["expression"][0]["columnName"]);
+
+        let payload = to_payload(&req, SerDeVersion::V3).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        assert!(val["constraints"].get("limit").is_none());
+        assert!(val["constraints"].get("orderByClause").is_none());
+        assert_eq!(
+            1,
+            val["constraints"]["expression"].as_array().unwrap().len()
+        );
+
+        let payload = to_payload(&req, SerDeVersion::V2).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(serde_json::json!({ "summary": {} }), val["constraints"]);
+    }
+
+    #[test]
+    fn test_payload_passthrough() {
+        let mut arguments = HashMap::new();
+        arguments.insert("QUERY".to_string(), "SELECT 1".to_string());
+        let constraints = Constraints::default()
+            .with_limit(100)
+            .with_query_passthrough_arguments(arguments.clone());
+        let req = ReadRecordRequest::new(
+            "query_id".to_string(),
+            "catalog".to_string(),
+            TableName::default(),
+            Schema::default(),
+            Split::default(),
+            constraints,
+        );
+        let payload = to_payload(&req, SerDeVersion::V5).unwrap();
+        let val: Value = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            "SELECT 1",
+            val["constraints"]["queryPassthroughArguments"]["QUERY"]
+        );
+        match to_payload(&req, SerDeVersion::V4) {
+            Err(crate::error::Error::Unsupported(_)) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+
+        let req = GetTableRequest::new(
+            "catalog".to_string(),
+            "schema".to_string(),
+            "table".to_string(),
+        );
+        assert!(to_payload(&req, SerDeVersion::V1).is_ok());
+        let req = req.with_query_passthrough_arguments(arguments);
+        assert!(to_payload(&req, SerDeVersion::V5).is_ok());
+        match to_payload(&req, SerDeVersion::V4) {
+            Err(crate::error::Error::Unsupported(_)) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_federation_response() {
+        let json = r#"{
+                "@type": "ListSchemasResponse",
+                "requestType": "LIST_SCHEMAS",
+                "catalogName": "catalog",
+                "schemas": ["a", "b"]
+            }"#;
+        let res: FederationResponse = serde_json::from_str(json).unwrap();
+        match &res {
+            FederationResponse::ListSchemas(r) => assert_eq!(vec!["a", "b"], r.schemas),
+            r => panic!("Unexpected response {:?}", r),
+        }
+
+        let val: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(val, serde_json::to_value(&res).unwrap());
+
+        let res: FederationResponse = serde_json::from_value(serde_json::json!({
+            "@type": "PingResponse",
+            "catalogName": "catalog",
+            "queryId": "query_id",
+            "sourceType": "cloudwatch"
+        }))
+        .unwrap();
+        match res {
+            FederationResponse::Ping(r) => assert_eq!("cloudwatch", r.source_type),
+            r => panic!("Unexpected response {:?}", r),
+        }
+
+        assert!(serde_json::from_str::<FederationResponse>(r#"{"@type": "Unknown"}"#).is_err());
+    }
+
+    #[test]
+    fn test_data_source_capabilities() {
+        let req = FederationRequest::GetDataSourceCapabilities(
+            GetDataSourceCapabilitiesRequest::new("query_id".to_string(), "catalog".to_string()),
+        );
+        let val = serde_json::to_value(&req).unwrap();
+        assert_eq!("GetDataSourceCapabilitiesRequest", val["@type"]);
+        assert_eq!("catalog", val["catalogName"]);
+
+        let res: FederationResponse = serde_json::from_value(serde_json::json!({
+            "@type": "GetDataSourceCapabilitiesResponse",
+            "catalogName": "catalog",
+            "capabilities": {
+                "supports_limit_pushdown": [{ "subType": "integer_constant" }]
+            }
+        }))
+        .unwrap();
+        let capabilities: Capabilities = match res {
+            FederationResponse::GetDataSourceCapabilities(r) => r.into(),
+            r => panic!("Unexpected response {:?}", r),
+        };
+        assert!(capabilities.supports_limit());
+        assert!(!capabilities.supports_top_n());
     }
 }
@@ -0,0 +1,278 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::error::{ConnectorError, Error};
+use rand::Rng;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Exceptions raised by connectors that indicate that the request may succeed
+/// if it is retried.
+const RETRYABLE_ERROR_TYPES: &[&str] = &[
+    "ThrottlingException",
+    "TooManyRequestsException",
+    "ServiceException",
+    "RateExceededException",
+];
+
+/// Classifies errors into retryable and non-retryable errors.
+pub type Classifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+/// Event reported to the observer of a `RetryPolicy`. The attempts are
+/// numbered starting with 1 for the first invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryEvent {
+    /// The attempt is started.
+    Attempt(u32),
+    /// The attempt failed with a retryable error and is retried after the delay.
+    Retry(u32, Duration),
+    /// The attempt failed with a retryable error, but it was the last attempt.
+    GiveUp(u32),
+}
+
+/// Receives the name of the invoked function and the events of its retries,
+/// e.g. to export them as metrics.
+pub type Observer = Arc<dyn Fn(&str, RetryEvent) + Send + Sync>;
+
+/// Policy for retrying failed invocations of the lambda functions with
+/// exponential backoff. The n-th retry is delayed by `base_delay * 2^(n-1)`,
+/// bounded by `max_delay`. With a jitter of `j`, the delay is randomly reduced
+/// by up to `j` times the delay.
+///
+/// By default, transient transport errors and connector errors whose type
+/// ends with one of the retryable error types are retried.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retryable_error_types: Vec<String>,
+    classifier: Option<Classifier>,
+    observer: Option<Observer>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            retryable_error_types: RETRYABLE_ERROR_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+            classifier: None,
+            observer: None,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("base_delay", &self.base_delay)
+            .field("max_delay", &self.max_delay)
+            .field("jitter", &self.jitter)
+            .field("retryable_error_types", &self.retryable_error_types)
+            .field("classifier", &self.classifier.is_some())
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries a failed invocation.
+    pub fn none() -> Self {
+        RetryPolicy::default().max_attempts(1)
+    }
+
+    /// Sets the maximum number of attempts, including the first invocation.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Sets the upper bound of the delay between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Sets the fraction between 0 and 1 by which the delays are randomly
+    /// reduced, to avoid that concurrent invocations retry in lockstep.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.max(0.0).min(1.0);
+        self
+    }
+
+    /// Sets the connector error types that are retried. A connector error is
+    /// retried if its type or the type of one of its causes ends with one of
+    /// the given types.
+    pub fn retryable_error_types(mut self, types: Vec<String>) -> Self {
+        self.retryable_error_types = types;
+        self
+    }
+
+    /// Replaces the default classification of errors with the given function.
+    pub fn classifier<F>(mut self, classifier: F) -> Self
+    where
+        F: Fn(&Error) -> bool + Send + Sync + 'static,
+    {
+        self.classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Calls the observer for every attempt, retry and give-up of the
+    /// invocations using the policy. The observer is shared by all clones of
+    /// the policy.
+    pub fn observer<F>(mut self, observer: F) -> Self
+    where
+        F: Fn(&str, RetryEvent) + Send + Sync + 'static,
+    {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Reports the event of an invocation of the function to the observer.
+    pub(crate) fn observe(&self, function_name: &str, event: RetryEvent) {
+        if let Some(observer) = &self.observer {
+            observer(function_name, event);
+        }
+    }
+
+    /// Returns true if the invocation that failed with the error is retried.
+    pub fn is_retryable(&self, e: &Error) -> bool {
+        if let Some(classifier) = &self.classifier {
+            return classifier(e);
+        }
+
+        match e {
+            Error::Transient(_) => true,
+            Error::Connector(e) => self.is_retryable_connector_error(e),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the given attempt failed with a retryable error and
+    /// the maximum number of attempts is not reached yet.
+    pub(crate) fn should_retry(&self, attempt: u32, e: &Error) -> bool {
+        attempt < self.max_attempts && self.is_retryable(e)
+    }
+
+    fn is_retryable_connector_error(&self, e: &ConnectorError) -> bool {
+        let retryable = self
+            .retryable_error_types
+            .iter()
+            .any(|t| e.error_type.ends_with(t.as_str()));
+        retryable
+            || e.cause
+                .as_ref()
+                .map_or(false, |cause| self.is_retryable_connector_error(cause))
+    }
+
+    /// Returns the delay before the given retry, starting with 1 for the
+    /// first retry.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if self.jitter > 0.0 {
+            let reduction = rand::thread_rng().gen_range(0.0, self.jitter);
+            delay.mul_f64(1.0 - reduction)
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn connector_error(error_type: &str) -> Error {
+        Error::Connector(ConnectorError {
+            error_type: error_type.to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(1000))
+            .jitter(0.0);
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(400), policy.delay(3));
+        assert_eq!(Duration::from_millis(1000), policy.delay(5));
+        assert_eq!(Duration::from_millis(1000), policy.delay(100));
+
+        let jittered = policy.clone().jitter(0.5);
+        for retry in 1..5 {
+            let delay = jittered.delay(retry);
+            assert!(delay <= policy.delay(retry));
+            assert!(delay >= policy.delay(retry) / 2);
+        }
+    }
+
+    #[test]
+    fn test_classification() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&Error::Transient("throttled".to_string())));
+        assert!(!policy.is_retryable(&Error::Transport("not found".to_string())));
+        assert!(!policy.is_retryable(&Error::Protocol("invalid".to_string())));
+        assert!(policy.is_retryable(&connector_error(
+            "com.amazonaws.services.cloudwatch.model.ThrottlingException"
+        )));
+        assert!(!policy.is_retryable(&connector_error("java.lang.RuntimeException")));
+
+        let caused = Error::Connector(ConnectorError {
+            error_type: "java.lang.RuntimeException".to_string(),
+            cause: Some(Box::new(ConnectorError {
+                error_type: "ThrottlingException".to_string(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        });
+        assert!(policy.is_retryable(&caused));
+
+        let policy = policy.retryable_error_types(vec!["RuntimeException".to_string()]);
+        assert!(policy.is_retryable(&connector_error("java.lang.RuntimeException")));
+
+        let policy = policy.classifier(|e| match e {
+            Error::Transport(_) => true,
+            _ => false,
+        });
+        assert!(policy.is_retryable(&Error::Transport("not found".to_string())));
+        assert!(!policy.is_retryable(&Error::Transient("throttled".to_string())));
+    }
+}
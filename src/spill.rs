@@ -0,0 +1,383 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use super::error::{Error, Result};
+use super::models;
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatch;
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use rusoto_core::Region;
+use rusoto_s3::{GetObjectRequest, ListObjectsV2Request, S3Client, S3};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Minimal interface of the object store the lambda functions spill their
+/// results to. The production implementation is backed by S3, but the trait
+/// allows to read spilled results from any other store, e.g. in tests.
+pub trait ObjectStore: Send + Sync {
+    /// Returns the keys of all objects in the bucket starting with the given prefix.
+    fn list(&self, bucket: &str, prefix: &str) -> BoxFuture<'static, Result<Vec<String>>>;
+
+    /// Returns the content of the object stored under the given key.
+    fn get(&self, bucket: &str, key: &str) -> BoxFuture<'static, Result<Vec<u8>>>;
+}
+
+/// `ObjectStore` implementation using the S3 API.
+#[derive(Clone)]
+pub struct S3ObjectStore {
+    client: S3Client,
+}
+
+impl S3ObjectStore {
+    pub fn new(region: Region) -> Self {
+        S3ObjectStore {
+            client: S3Client::new(region),
+        }
+    }
+}
+
+/// Lists the keys of all objects starting with the prefix, following the
+/// continuation tokens of truncated listings.
+async fn list_objects(client: S3Client, bucket: String, prefix: String) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let req = ListObjectsV2Request {
+            bucket: bucket.clone(),
+            prefix: Some(prefix.clone()),
+            continuation_token: continuation_token.take(),
+            ..Default::default()
+        };
+        trace!("Listing spilled objects in s3://{}/{}", bucket, prefix);
+        let res = client
+            .list_objects_v2(req)
+            .await
+            .map_err(|e| Error::Spill(e.to_string()))?;
+        for object in res.contents.unwrap_or_default() {
+            if let Some(key) = object.key {
+                keys.push(key);
+            }
+        }
+
+        match res.next_continuation_token {
+            Some(token) if res.is_truncated.unwrap_or(false) => continuation_token = Some(token),
+            _ => break,
+        }
+    }
+    Ok(keys)
+}
+
+/// Fetches the content of the object by collecting the chunks of its body.
+async fn get_object(client: S3Client, bucket: String, key: String) -> Result<Vec<u8>> {
+    let req = GetObjectRequest {
+        bucket: bucket.clone(),
+        key: key.clone(),
+        ..Default::default()
+    };
+    trace!("Fetching spilled object s3://{}/{}", bucket, key);
+    let res = client
+        .get_object(req)
+        .await
+        .map_err(|e| Error::Spill(e.to_string()))?;
+    let body = res
+        .body
+        .ok_or_else(|| Error::Spill(format!("Empty object s3://{}/{}", bucket, key)))?;
+    body.map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .map_err(|e| Error::Spill(e.to_string()))
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn list(&self, bucket: &str, prefix: &str) -> BoxFuture<'static, Result<Vec<String>>> {
+        list_objects(self.client.clone(), bucket.to_string(), prefix.to_string()).boxed()
+    }
+
+    fn get(&self, bucket: &str, key: &str) -> BoxFuture<'static, Result<Vec<u8>>> {
+        get_object(self.client.clone(), bucket.to_string(), key.to_string()).boxed()
+    }
+}
+
+/// In-memory `ObjectStore` that can be used as a stand-in for S3.
+#[derive(Default)]
+pub struct InMemoryObjectStore {
+    objects: Mutex<BTreeMap<(String, String), Vec<u8>>>,
+}
+
+impl InMemoryObjectStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the content under the given bucket and key.
+    pub fn put(&self, bucket: &str, key: &str, content: Vec<u8>) {
+        self.objects
+            .lock()
+            .unwrap()
+            .insert((bucket.to_string(), key.to_string()), content);
+    }
+}
+
+impl ObjectStore for InMemoryObjectStore {
+    fn list(&self, bucket: &str, prefix: &str) -> BoxFuture<'static, Result<Vec<String>>> {
+        let keys = self
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(b, k)| b == bucket && k.starts_with(prefix))
+            .map(|(_, k)| k.clone())
+            .collect();
+        future::ok(keys).boxed()
+    }
+
+    fn get(&self, bucket: &str, key: &str) -> BoxFuture<'static, Result<Vec<u8>>> {
+        let content = self
+            .objects
+            .lock()
+            .unwrap()
+            .get(&(bucket.to_string(), key.to_string()))
+            .cloned()
+            .ok_or_else(|| Error::Spill(format!("No such object {}/{}", bucket, key)));
+        future::ready(content).boxed()
+    }
+}
+
+/// The SDK appends a sequence number to the key of every spilled block. To
+/// return the blocks in the order they were written, keys with a numeric
+/// suffix are compared by the numeric value of the suffix.
+fn spill_order(key: &str) -> (Option<u64>, &str) {
+    let suffix = key.rsplit(|c| c == '.' || c == '/').next();
+    (suffix.and_then(|s| s.parse().ok()), key)
+}
+
+/// Returns the keys of all objects referenced by the spill location in the
+/// order they were spilled.
+async fn spill_keys(
+    store: &dyn ObjectStore,
+    location: &models::SpillLocation,
+) -> Result<Vec<String>> {
+    if !location.is_directory() {
+        return Ok(vec![location.key().to_string()]);
+    }
+
+    // Without the trailing slash, the prefix `split1` would also match the
+    // objects of `split10`.
+    let prefix = format!("{}/", location.key().trim_end_matches('/'));
+    let mut keys = store.list(location.bucket(), &prefix).await?;
+    keys.sort_by(|a, b| spill_order(a).cmp(&spill_order(b)));
+    Ok(keys)
+}
+
+/// Fetches, decrypts and decodes the spilled block with the given key.
+async fn fetch(
+    store: Arc<dyn ObjectStore>,
+    bucket: String,
+    key: String,
+    schema: Arc<Schema>,
+    encryption_key: Option<models::EncryptionKey>,
+) -> Result<RecordBatch> {
+    let batch = async {
+        let mut content = store.get(&bucket, &key).await?;
+        if let Some(encryption_key) = &encryption_key {
+            content = encryption_key.decrypt(&content)?;
+        }
+        models::decode_record_batch(&content, schema)
+    }
+    .await;
+    if let Err(e) = &batch {
+        error!(
+            "Could not read spilled block s3://{}/{}: {}",
+            bucket, key, e
+        );
+    }
+    batch
+}
+
+/// The `SpillReader` fetches the blocks a lambda function spilled to the
+/// object store and decodes them into Arrow `RecordBatch`es.
+#[derive(Clone)]
+pub struct SpillReader {
+    store: Arc<dyn ObjectStore>,
+}
+
+/// Stream over the spilled blocks of a single `SpillLocation`.
+pub type SpilledBlocks = BoxStream<'static, Result<RecordBatch>>;
+
+impl SpillReader {
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        SpillReader { store }
+    }
+
+    /// Returns the keys of all objects referenced by the spill location in the
+    /// order they were spilled. If the location is not a directory, only the
+    /// key of the location itself is returned.
+    pub async fn keys(&self, location: &models::SpillLocation) -> Result<Vec<String>> {
+        spill_keys(self.store.as_ref(), location).await
+    }
+
+    /// Returns a stream over the blocks spilled to the given location. The
+    /// objects are fetched lazily while polling and decrypted with the key,
+    /// if the lambda function encrypted them.
+    pub fn read(
+        &self,
+        location: &models::SpillLocation,
+        schema: Arc<Schema>,
+        key: Option<&models::EncryptionKey>,
+    ) -> SpilledBlocks {
+        let keys = {
+            let store = self.store.clone();
+            let location = location.clone();
+            async move { spill_keys(store.as_ref(), &location).await }
+        };
+        let store = self.store.clone();
+        let bucket = location.bucket().to_string();
+        let key = key.cloned();
+        stream::once(keys)
+            .map_ok(|keys| stream::iter(keys.into_iter().map(Ok)))
+            .try_flatten()
+            .and_then(move |object_key| {
+                fetch(
+                    store.clone(),
+                    bucket.clone(),
+                    object_key,
+                    schema.clone(),
+                    key.clone(),
+                )
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::test_util::{RECORDS, SCHEMA};
+    use futures::executor::block_on;
+
+    fn setup() -> (Arc<InMemoryObjectStore>, Arc<Schema>) {
+        let store = Arc::new(InMemoryObjectStore::new());
+        let records = base64::decode(RECORDS).unwrap();
+        for i in &[0, 1, 2, 10] {
+            store.put("bucket", &format!("spill/split.{}", i), records.clone());
+        }
+        store.put("bucket", "other/split.0", records);
+
+        let schema = models::Schema::new_from_string(SCHEMA.to_string())
+            .get_schema()
+            .unwrap();
+        (store, Arc::new(schema))
+    }
+
+    #[test]
+    fn test_spill_order() {
+        let (store, _) = setup();
+        let reader = SpillReader::new(store);
+        let location = models::SpillLocation::new("bucket".to_string(), "spill".to_string(), true);
+        assert_eq!(
+            vec![
+                "spill/split.0",
+                "spill/split.1",
+                "spill/split.2",
+                "spill/split.10"
+            ],
+            block_on(reader.keys(&location)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sibling_prefix() {
+        let store = Arc::new(InMemoryObjectStore::new());
+        for key in &[
+            "spill/split1/0",
+            "spill/split1/1",
+            "spill/split10/0",
+            "spill/split11/0",
+        ] {
+            store.put("bucket", key, vec![]);
+        }
+        let reader = SpillReader::new(store);
+        let location =
+            models::SpillLocation::new("bucket".to_string(), "spill/split1".to_string(), true);
+        assert_eq!(
+            vec!["spill/split1/0", "spill/split1/1"],
+            block_on(reader.keys(&location)).unwrap()
+        );
+        let location =
+            models::SpillLocation::new("bucket".to_string(), "spill/split1/".to_string(), true);
+        assert_eq!(2, block_on(reader.keys(&location)).unwrap().len());
+    }
+
+    #[test]
+    fn test_read_directory() {
+        let (store, schema) = setup();
+        let reader = SpillReader::new(store);
+        let location = models::SpillLocation::new("bucket".to_string(), "spill".to_string(), true);
+        let batches: Vec<RecordBatch> =
+            block_on(reader.read(&location, schema, None).try_collect()).unwrap();
+        assert_eq!(4, batches.len());
+        for batch in batches {
+            assert_eq!(1, batch.num_rows());
+            assert_eq!(3, batch.num_columns());
+        }
+    }
+
+    #[test]
+    fn test_read_single_object() {
+        let (store, schema) = setup();
+        let reader = SpillReader::new(store);
+        let location =
+            models::SpillLocation::new("bucket".to_string(), "spill/split.1".to_string(), false);
+        assert_eq!(1, block_on(reader.read(&location, schema, None).count()));
+    }
+
+    #[test]
+    fn test_read_encrypted() {
+        let (store, schema) = setup();
+        let key = models::EncryptionKey::generate().unwrap();
+        let records = base64::decode(RECORDS).unwrap();
+        store.put(
+            "bucket",
+            "encrypted/split.0",
+            key.encrypt(&records).unwrap(),
+        );
+        store.put("bucket", "tampered/split.0", records);
+
+        let reader = SpillReader::new(store);
+        let location =
+            models::SpillLocation::new("bucket".to_string(), "encrypted".to_string(), true);
+        let batches: Vec<RecordBatch> = block_on(
+            reader
+                .read(&location, schema.clone(), Some(&key))
+                .try_collect(),
+        )
+        .unwrap();
+        assert_eq!(1, batches.len());
+        assert_eq!(1, batches[0].num_rows());
+
+        // Blocks which were not encrypted with the key are rejected
+        let location =
+            models::SpillLocation::new("bucket".to_string(), "tampered".to_string(), true);
+        let mut blocks = reader.read(&location, schema, Some(&key));
+        match block_on(blocks.next()) {
+            Some(Err(Error::Encryption(_))) => {}
+            r => panic!("Unexpected result {:?}", r.map(|b| b.map(|b| b.num_rows()))),
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+//! Fixtures shared by the unit tests of the crate. The block has a single
+//! row of the columns `log_group`, `log_stream_bytes` and `log_stream`.
+
+use crate::error::Result;
+use crate::invoker::InvocationResult;
+
+/// Arrow IPC schema message of the fixture block.
+pub(crate) const SCHEMA: &str = "/////xABAAAQAAAAAAAKAA4ABgANAAgACgAAAAAAAwAQAAAAAAEKAAwAAAAIAAQACgAAAAgAAAAIAAAAAAAAAAMAAACcAAAAPAAAAAQAAACC////FAAAABQAAAAUAAAAAAAFARAAAAAAAAAAAAAAAHD///8JAAAAbG9nX2dyb3VwAAAAtv///xQAAAAUAAAAHAAAAAAAAgEgAAAAAAAAAAAAAAAIAAwACAAHAAgAAAAAAAABQAAAABAAAABsb2dfc3RyZWFtX2J5dGVzAAASABgAFAATABIADAAAAAgABAASAAAAFAAAABQAAAAYAAAAAAAFARQAAAAAAAAAAAAAAAQABAAEAAAACgAAAGxvZ19zdHJlYW0AAA==";
+/// Arrow IPC record batch message of the fixture block.
+pub(crate) const RECORDS: &str = "/////wgBAAAUAAAAAAAAAAwAFgAOABUAEAAEAAwAAACAAAAAAAAAAAAAAwAQAAAAAAMKABgADAAIAAQACgAAABQAAACYAAAAAQAAAAAAAAAAAAAACAAAAAAAAAAAAAAAAQAAAAAAAAAIAAAAAAAAAAgAAAAAAAAAEAAAAAAAAAA0AAAAAAAAAEgAAAAAAAAAAQAAAAAAAABQAAAAAAAAAAgAAAAAAAAAWAAAAAAAAAABAAAAAAAAAGAAAAAAAAAACAAAAAAAAABoAAAAAAAAABIAAAAAAAAAAAAAAAMAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAAAAAAAEAAAAAAAAAAAAAAAAAAAABAAAAAAAAAAAAAAA0AAAAMjAxOS8xMS8xNi9bJExBVEVTVF0wNTM0NmI2MTExMWI0YWQ2OTZkOTRiYTYwZTQ3MzRiNgAAAAABAAAAAAAAAAAAAAAAAAAAAQAAAAAAAAAAAAAAEgAAAC9hd3MvbGFtYmRhL2N3dGVzdAAAAAAAAA==";
+
+/// Returns the serialized fixture block.
+pub(crate) fn block_json() -> serde_json::Value {
+    serde_json::json!({
+        "schema": SCHEMA,
+        "records": RECORDS,
+        "aId": "52fb8f5f-e2d0-4345-84d4-5f651bee361b"
+    })
+}
+
+/// Returns a successful invocation with the serialized value as payload.
+pub(crate) fn result(value: serde_json::Value) -> Result<InvocationResult> {
+    Ok(InvocationResult {
+        payload: serde_json::to_vec(&value).unwrap(),
+        function_error: None,
+    })
+}
+
+/// Returns a serialized split spilled to the given key of `bucket`.
+pub(crate) fn split_json(key: &str) -> serde_json::Value {
+    serde_json::json!({
+        "spillLocation": {
+            "@type": "S3SpillLocation",
+            "bucket": "bucket",
+            "key": key,
+            "directory": true
+        },
+        "encryptionKey": null,
+        "properties": {}
+    })
+}
@@ -18,7 +18,8 @@
 extern crate rust_lambda_fed;
 
 use rlf::models::*;
-use rlf::{Configuration, Planner, Executor};
+use rlf::requests::ReadRecordRequest;
+use rlf::{Configuration, Executor, Planner};
 use rust_lambda_fed as rlf;
 
 extern crate pretty_env_logger;
@@ -48,8 +49,8 @@ fn setup() -> TestConfig {
 #[test]
 fn test_list_schemas() {
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    let schemas = p.list_schemas();
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let schemas = p.list_schemas().unwrap();
     assert!(!schemas.schemas.is_empty());
 }
 
@@ -57,11 +58,11 @@ fn test_list_schemas() {
 fn test_list_tables() {
     println!("YEs");
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    let schema_response = p.list_schemas();
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let schema_response = p.list_schemas().unwrap();
     println!("YEs");
     for schema in &schema_response.schemas {
-        let tables = p.list_tables("".to_owned(), schema.clone());
+        let tables = p.list_tables("".to_owned(), schema.clone()).unwrap();
         for t in &tables.tables {
             debug!("{:?}", t);
         }
@@ -71,23 +72,27 @@ fn test_list_tables() {
 #[test]
 fn test_get_table() {
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    dbg!(p.get_table(
-        "".to_owned(),
-        "/aws/lambda/cwtest".to_owned(),
-        "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
-    ));
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    dbg!(p
+        .get_table(
+            "".to_owned(),
+            "/aws/lambda/cwtest".to_owned(),
+            "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
+        )
+        .unwrap());
 }
 
 #[test]
 fn test_get_table_layout() {
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    let mut val = dbg!(p.get_table(
-        "".to_owned(),
-        "/aws/lambda/cwtest".to_owned(),
-        "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
-    ));
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let mut val = dbg!(p
+        .get_table(
+            "".to_owned(),
+            "/aws/lambda/cwtest".to_owned(),
+            "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
+        )
+        .unwrap());
 
     let schema = val.schema.get_schema().unwrap();
     let s = dbg!(schema.metadata()).get("partitionCols");
@@ -98,75 +103,138 @@ fn test_get_table_layout() {
         Constraints::default(),
         val.schema,
         vec![s.unwrap().clone()],
-    );
+    )
+    .unwrap();
 }
 
 #[test]
 fn test_get_splits() {
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    let mut val = dbg!(p.get_table(
-        "".to_owned(),
-        "/aws/lambda/cwtest".to_owned(),
-        "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
-    ));
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let mut val = dbg!(p
+        .get_table(
+            "".to_owned(),
+            "/aws/lambda/cwtest".to_owned(),
+            "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
+        )
+        .unwrap());
 
     let schema = val.schema.get_schema().unwrap();
     let s = dbg!(schema.metadata()).get("partitionCols");
 
-    let layout = p.get_table_layout(
-        val.catalog_name.clone(),
-        val.table_name.clone(),
-        Constraints::default(),
-        val.schema.clone(),
-        vec![s.unwrap().clone()],
-    );
-
-    let splits = dbg!(p.get_splits(
-        "".to_string(),
-        val.catalog_name,
-        val.table_name,
-        layout.partitions,
-        vec![s.unwrap().clone()],
-        Constraints::default(),
-        None,
-    ));
+    let layout = p
+        .get_table_layout(
+            val.catalog_name.clone(),
+            val.table_name.clone(),
+            Constraints::default(),
+            val.schema.clone(),
+            vec![s.unwrap().clone()],
+        )
+        .unwrap();
+
+    let splits = dbg!(p
+        .get_splits(
+            "".to_string(),
+            val.catalog_name,
+            val.table_name,
+            layout.partitions,
+            vec![s.unwrap().clone()],
+            Constraints::default(),
+            None,
+        )
+        .unwrap());
 }
 
 #[test]
-fn test_with_execution() {
+fn test_all_splits() {
     let c = setup();
-    let mut p = Planner::new(c.config.clone());
-    let mut val = dbg!(p.get_table(
-        "".to_owned(),
-        "/aws/lambda/cwtest".to_owned(),
-        "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
-    ));
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let mut val = dbg!(p
+        .get_table(
+            "".to_owned(),
+            "/aws/lambda/cwtest".to_owned(),
+            "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
+        )
+        .unwrap());
 
     let schema = val.schema.get_schema().unwrap();
     let s = dbg!(schema.metadata()).get("partitionCols");
 
-    let layout = p.get_table_layout(
-        val.catalog_name.clone(),
-        val.table_name.clone(),
-        Constraints::default(),
-        val.schema.clone(),
-        vec![s.unwrap().clone()],
-    );
+    let layout = p
+        .get_table_layout(
+            val.catalog_name.clone(),
+            val.table_name.clone(),
+            Constraints::default(),
+            val.schema.clone(),
+            vec![s.unwrap().clone()],
+        )
+        .unwrap();
+
+    let splits: Vec<Split> = p
+        .all_splits(
+            "".to_string(),
+            val.catalog_name,
+            val.table_name,
+            layout.partitions,
+            vec![s.unwrap().clone()],
+            Constraints::default(),
+            Some(10),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(!splits.is_empty());
+}
 
-    let splits = dbg!(p.get_splits(
-        "".to_string(),
-        val.catalog_name,
-        val.table_name,
-        layout.partitions,
-        vec![s.unwrap().clone()],
-        Constraints::default(),
-        None,
-    ));
+#[test]
+fn test_with_execution() {
+    let c = setup();
+    let mut p = Planner::new(c.config.clone()).unwrap();
+    let mut val = dbg!(p
+        .get_table(
+            "".to_owned(),
+            "/aws/lambda/cwtest".to_owned(),
+            "2019/11/16/[$latest]05346b61111b4ad696d94ba60e4734b6".to_owned(),
+        )
+        .unwrap());
 
-    let mut e = Executor::new(c.config.clone());
-    // For each Split call the executor
-    
-    
+    let schema = val.schema.get_schema().unwrap();
+    let s = dbg!(schema.metadata()).get("partitionCols");
 
+    let layout = p
+        .get_table_layout(
+            val.catalog_name.clone(),
+            val.table_name.clone(),
+            Constraints::default(),
+            val.schema.clone(),
+            vec![s.unwrap().clone()],
+        )
+        .unwrap();
+
+    let splits = dbg!(p
+        .get_splits(
+            "".to_string(),
+            val.catalog_name.clone(),
+            val.table_name.clone(),
+            layout.partitions,
+            vec![s.unwrap().clone()],
+            Constraints::default(),
+            None,
+        )
+        .unwrap());
+
+    let mut e = Executor::new(c.config.clone()).unwrap();
+    // For each Split call the executor
+    for split in splits.into_splits() {
+        let req = ReadRecordRequest::new(
+            "".to_string(),
+            val.catalog_name.clone(),
+            val.table_name.clone(),
+            val.schema.clone(),
+            split,
+            Constraints::default(),
+        );
+        for batch in e.read_records(req).unwrap() {
+            debug!("Read {} rows", batch.num_rows());
+        }
+    }
 }